@@ -0,0 +1,91 @@
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// How to find the application the picker was opened from
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppDetection {
+    /// X11 on Linux, System Events on macOS
+    #[default]
+    Auto,
+    /// WM_CLASS of the X11 `_NET_ACTIVE_WINDOW`, via `xprop`
+    X11,
+    /// Bundle identifier of the frontmost application, via `osascript`
+    MacOs,
+    /// A shell command printing the application name, for other window
+    /// systems (e.g. `swaymsg` on Wayland)
+    Command(String),
+    /// Don't detect the application; ranking is global
+    None,
+}
+
+/// A source for the name of the active application
+pub trait AppProvider {
+    fn active_app(&self) -> Option<String>;
+}
+
+pub struct X11;
+
+impl AppProvider for X11 {
+    fn active_app(&self) -> Option<String> {
+        // `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3e00007`
+        let active = output("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+        let window = active.split_whitespace().next_back().filter(|id| id.starts_with("0x"))?;
+        // `WM_CLASS(STRING) = "Navigator", "firefox"`: instance, then class
+        let class = output("xprop", &["-id", window, "WM_CLASS"])?;
+        let (_, values) = class.split_once('=')?;
+        values.rsplit(',').next().map(|v| v.trim().trim_matches('"').to_lowercase())
+    }
+}
+
+pub struct MacOs;
+
+impl AppProvider for MacOs {
+    fn active_app(&self) -> Option<String> {
+        output(
+            "osascript",
+            &[
+                "-e",
+                "tell application \"System Events\" to get bundle identifier of first application process whose frontmost is true",
+            ],
+        )
+    }
+}
+
+/// Runs a user-configured shell command and uses its output
+pub struct ShellCommand(pub String);
+
+impl AppProvider for ShellCommand {
+    fn active_app(&self) -> Option<String> {
+        output("sh", &["-c", &self.0])
+    }
+}
+
+/// Trimmed standard output of a successful command, if not empty
+pub fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The provider for a detection setting, if any
+pub fn provider(detection: &AppDetection) -> Option<Box<dyn AppProvider>> {
+    match detection {
+        AppDetection::Auto if cfg!(target_os = "macos") => Some(Box::new(MacOs)),
+        AppDetection::Auto if std::env::var_os("DISPLAY").is_some() => Some(Box::new(X11)),
+        AppDetection::Auto | AppDetection::None => None,
+        AppDetection::X11 => Some(Box::new(X11)),
+        AppDetection::MacOs => Some(Box::new(MacOs)),
+        AppDetection::Command(command) => Some(Box::new(ShellCommand(command.clone()))),
+    }
+}
+
+/// The application active right now. Called before the picker window
+/// opens, so this is the application the emoji will be pasted into.
+pub fn detect(detection: &AppDetection) -> Option<String> {
+    provider(detection)?.active_app()
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_json, save_json};
+use crate::history::QueryCodeCount;
+use crate::index::{find_code, EmojiRecord};
+use crate::locale::{fold, normalize_code};
+use crate::query::{Query, Term};
+
+const ALIASES_FILE: &str = "aliases.json";
+
+/// Aliases added or removed by hand, kept in `~/.emoru/aliases.json` as
+/// alias → emoji codes
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AliasEdits {
+    pub added: BTreeMap<String, Vec<String>>,
+    /// Learned aliases that were removed, so they are not learned again
+    pub removed: BTreeMap<String, Vec<String>>,
+}
+
+fn contains(map: &BTreeMap<String, Vec<String>>, alias: &str, code: &str) -> bool {
+    let code = normalize_code(code);
+    map.get(alias).is_some_and(|codes| codes.iter().any(|c| normalize_code(c) == code))
+}
+
+fn insert(map: &mut BTreeMap<String, Vec<String>>, alias: &str, code: &str) {
+    if !contains(map, alias, code) {
+        map.entry(alias.to_string()).or_default().push(code.to_string());
+    }
+}
+
+fn remove(map: &mut BTreeMap<String, Vec<String>>, alias: &str, code: &str) {
+    let Some(codes) = map.get_mut(alias) else {
+        return;
+    };
+    let code = normalize_code(code);
+    codes.retain(|c| normalize_code(c) != code);
+    if codes.is_empty() {
+        map.remove(alias);
+    }
+}
+
+impl AliasEdits {
+    /// Load the aliases file, falling back to no edits if it is missing or invalid
+    pub fn load() -> Self {
+        load_json(ALIASES_FILE)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        save_json(ALIASES_FILE, self)
+    }
+
+    /// Make `alias` find an emoji, undoing an earlier removal
+    pub fn add(&mut self, alias: &str, code: &str) {
+        remove(&mut self.removed, alias, code);
+        insert(&mut self.added, alias, code);
+    }
+
+    /// Stop `alias` from finding an emoji, whether it was added by hand or
+    /// learned, and keep it from being learned again
+    pub fn remove(&mut self, alias: &str, code: &str) {
+        remove(&mut self.added, alias, code);
+        insert(&mut self.removed, alias, code);
+    }
+}
+
+/// Where an alias comes from
+pub enum Source {
+    /// Learned from this many selections
+    Learned(u32),
+    Added,
+}
+
+/// A query that finds an emoji its keywords don't describe
+pub struct Alias {
+    pub alias: String,
+    pub code: String,
+    pub source: Source,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Learned(count) => write!(f, "learned from {} selections", count),
+            Source::Added => write!(f, "added"),
+        }
+    }
+}
+
+/// Alias text as matched: folded, with single spaces between words
+pub fn normalize(alias: &str) -> String {
+    alias.split_whitespace().map(fold).collect::<Vec<_>>().join(" ")
+}
+
+/// Whether a query is only plain search terms, at least one of which does
+/// not start any keyword of the entry: the entry was found by a loose fuzzy
+/// match, or picked from the fallback results when nothing matched
+fn matches_poorly(query: &str, entry: &EmojiRecord) -> bool {
+    let Ok(parsed) = Query::parse(query) else {
+        return false;
+    };
+    let plain = parsed
+        .clauses
+        .iter()
+        .all(|clause| !clause.negated && matches!(clause.term, Term::Fuzzy(_)));
+    plain
+        && parsed.clauses.iter().any(|clause| match &clause.term {
+            Term::Fuzzy(term) => !entry.words.iter().any(|word| word.starts_with(term.as_str())),
+            _ => false,
+        })
+}
+
+/// Every alias: those added by hand, and queries that found an emoji poorly
+/// in at least `threshold` selections (none when it is 0), minus removed ones.
+/// Sorted by alias.
+pub fn aliases(counts: &[QueryCodeCount], emojis: &[EmojiRecord], edits: &AliasEdits, threshold: u32) -> Vec<Alias> {
+    let mut aliases: Vec<Alias> = edits
+        .added
+        .iter()
+        .flat_map(|(alias, codes)| {
+            codes.iter().map(|code| Alias { alias: alias.clone(), code: code.clone(), source: Source::Added })
+        })
+        .collect();
+
+    if threshold > 0 {
+        let mut learned: BTreeMap<(String, String), u32> = BTreeMap::new();
+        for pair in counts {
+            let alias = normalize(&pair.query);
+            if !alias.is_empty() {
+                *learned.entry((alias, normalize_code(&pair.code))).or_insert(0) += pair.count;
+            }
+        }
+
+        for ((alias, code), count) in learned {
+            if count < threshold || contains(&edits.removed, &alias, &code) || contains(&edits.added, &alias, &code) {
+                continue;
+            }
+            if let Some(entry) = find_code(emojis, &code).filter(|entry| matches_poorly(&alias, entry)) {
+                aliases.push(Alias { alias, code: entry.code.clone(), source: Source::Learned(count) });
+            }
+        }
+    }
+
+    aliases.sort_by(|a, b| a.alias.cmp(&b.alias));
+    aliases
+}
+
+/// Make aliases searchable by adding their words to the emoji's keywords
+pub fn apply(emojis: &mut [EmojiRecord], aliases: &[Alias]) {
+    for alias in aliases {
+        let code = normalize_code(&alias.code);
+        let Some(entry) = emojis.iter_mut().find(|r| normalize_code(&r.code) == code) else {
+            continue;
+        };
+        for word in alias.alias.split_whitespace() {
+            if !entry.words.iter().any(|w| w == word) {
+                entry.words.push(word.to_string());
+            }
+        }
+        if !entry.aliases.contains(&alias.alias) {
+            entry.aliases.push(alias.alias.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(query: &str, code: &str, count: u32) -> QueryCodeCount {
+        QueryCodeCount { query: query.to_string(), code: code.to_string(), count }
+    }
+
+    #[test]
+    fn learns_queries_that_find_an_emoji_poorly() {
+        let index: Vec<EmojiRecord> = ["😂| face with tears of joy | 1F602", "🎉| party popper | 1F389"]
+            .iter()
+            .filter_map(|line| EmojiRecord::parse(line))
+            .collect();
+        let counts = [
+            count("lol", "1F602", 2),
+            count("LOL ", "1F602", 1),
+            count("tears", "1F602", 5),
+            count("yay", "1F389", 2),
+            count("woo", "1F389", 3),
+        ];
+        let mut edits = AliasEdits::default();
+        edits.remove("woo", "1F389");
+        edits.add("tada", "1F389");
+
+        let found: Vec<String> = aliases(&counts, &index, &edits, 3)
+            .iter()
+            .map(|a| format!("{} {} {}", a.alias, a.code, a.source))
+            .collect();
+        // "tears" is already a keyword, "yay" is below the threshold and "woo" was removed
+        assert_eq!(found, ["lol 1F602 learned from 3 selections", "tada 1F389 added"]);
+        assert!(aliases(&counts, &index, &edits, 0).iter().all(|a| matches!(a.source, Source::Added)));
+    }
+}
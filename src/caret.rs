@@ -0,0 +1,94 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use atspi::proxy::accessible::{AccessibleProxy, ObjectRefExt};
+use atspi::proxy::bus::BusProxy;
+use atspi::proxy::proxy_ext::ProxyExt;
+use atspi::zbus::{self, Connection};
+use atspi::{CoordType, State};
+use futures_lite::future;
+
+use crate::placement::Rect;
+
+/// AT-SPI calls can hang for a long time when the accessibility bus is missing
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Objects looked at in the active window before giving up
+const SEARCH_BUDGET: usize = 5000;
+
+/// Bounds of the caret in the focused text field of the active window, in
+/// screen pixels, asked over the AT-SPI accessibility bus
+pub fn find() -> Option<Rect> {
+    let (sender, receiver) = mpsc::channel();
+    // Left behind if it hangs; the picker doesn't wait for it
+    thread::spawn(move || sender.send(future::block_on(caret())));
+    receiver.recv_timeout(TIMEOUT).ok().flatten()
+}
+
+async fn caret() -> Option<Rect> {
+    let session = Connection::session().await.ok()?;
+    let address = BusProxy::new(&session).await.ok()?.get_address().await.ok()?;
+    let bus = zbus::connection::Builder::address(address.as_str()).ok()?.build().await.ok()?;
+    let desktop = AccessibleProxy::builder(&bus)
+        .destination("org.a11y.atspi.Registry")
+        .ok()?
+        .build()
+        .await
+        .ok()?;
+
+    for app in desktop.get_children().await.ok()? {
+        let Ok(app) = app.into_accessible_proxy(&bus).await else {
+            continue;
+        };
+        for window in app.get_children().await.unwrap_or_default() {
+            let Ok(window) = window.into_accessible_proxy(&bus).await else {
+                continue;
+            };
+            if !window.get_state().await.is_ok_and(|states| states.contains(State::Active)) {
+                continue;
+            }
+            if let Some(field) = focused_field(&bus, window).await {
+                return text_caret(&field).await;
+            }
+        }
+    }
+    None
+}
+
+/// The focused editable object in `window`, searched through the objects being shown
+async fn focused_field<'a>(bus: &'a Connection, window: AccessibleProxy<'a>) -> Option<AccessibleProxy<'a>> {
+    let mut stack = vec![window];
+    for _ in 0..SEARCH_BUDGET {
+        let object = stack.pop()?;
+        let Ok(states) = object.get_state().await else {
+            continue;
+        };
+        if states.contains(State::Focused) && states.contains(State::Editable) {
+            return Some(object);
+        }
+        if states.contains(State::Showing) {
+            for child in object.get_children().await.unwrap_or_default() {
+                if let Ok(child) = child.into_accessible_proxy(bus).await {
+                    stack.push(child);
+                }
+            }
+        }
+    }
+    None
+}
+
+async fn text_caret(field: &AccessibleProxy<'_>) -> Option<Rect> {
+    let text = field.proxies().await.ok()?.text().await.ok()?;
+    let offset = text.caret_offset().await.ok()?;
+    let (x, y, width, height) = text.get_character_extents(offset, CoordType::Screen).await.ok()?;
+    if height > 0 {
+        return Some(Rect { x, y, width: width.max(1), height });
+    }
+    // There is no character after the caret at the end of the text: use the one before it
+    if offset == 0 {
+        return None;
+    }
+    let (x, y, width, height) = text.get_character_extents(offset - 1, CoordType::Screen).await.ok()?;
+    (height > 0).then_some(Rect { x: x + width, y, width: width.max(1), height })
+}
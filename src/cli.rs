@@ -0,0 +1,466 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+
+use crate::alias::{self, AliasEdits};
+use crate::config::Config;
+use crate::convert::{self, Form, Lexicon};
+use crate::emoticon::Emoticons;
+use crate::eval;
+use crate::favorites::Favorites;
+use crate::index::{find_data_dir, load_index};
+use crate::keymap::Keymap;
+use crate::locale::{self, resolve_locales};
+use crate::query::Query;
+use crate::ranker;
+use crate::index::{find_code, find_emoji, lookup};
+use crate::related::{CoOccurrence, CO_SELECTION_WINDOW_SECS};
+use crate::history::{current_timestamp, parse_date, read_events, ClearFilter, History, LogEvent, LogLevel};
+use crate::search;
+use crate::stats::{self, Period};
+use crate::store::{self, HistoryStore};
+
+/// Cross-platform emoji picker. Run without a command to open the picker.
+#[derive(Parser)]
+#[command(name = "emoru", version, about)]
+pub struct Cli {
+    /// Open the picker without recording anything to the history
+    #[arg(long)]
+    pub incognito: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Search the emoji index and print matches, best first
+    ///
+    /// Supports the same query syntax as the picker: `-term` to exclude,
+    /// `"red heart"` for a phrase, `=word` for an exact word and field
+    /// filters such as `group:animals`, `v:15` or `tone:`. Options go before
+    /// the query; start it with `--` when its first term is an exclusion.
+    Search {
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Rank as if the picker were opened from this application
+        #[arg(long)]
+        app: Option<String>,
+        /// Query terms
+        #[arg(required = true, trailing_var_arg = true)]
+        query: Vec<String>,
+    },
+    /// Replace emoticons and `:shortcodes:` with emoji, reading stdin and writing stdout
+    Emojify {
+        /// Convert this `demojify` notation back to emoji instead, leaving
+        /// emoticons and other text alone
+        #[arg(long, value_enum)]
+        form: Option<Form>,
+    },
+    /// Replace emoji with text, reading stdin and writing stdout
+    ///
+    /// `emoru emojify --form <FORM>` restores the original emoji.
+    Demojify {
+        /// Notation to write emoji in
+        #[arg(long, value_enum, default_value_t = Form::Shortcode)]
+        form: Form,
+    },
+    /// Show emoji often selected together with the given one
+    Related {
+        /// An emoji or its code (e.g. `1F389`)
+        emoji: String,
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Report usage statistics from the history
+    Stats {
+        /// Number of emoji to list overall, per period and as never used
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Period to break usage down by
+        #[arg(long, value_enum, default_value_t = Period::Month)]
+        period: Period,
+        /// Print the report as JSON, including every never-used emoji
+        #[arg(long)]
+        json: bool,
+    },
+    /// Replay recorded sessions to measure how well ranking strategies would have done
+    ///
+    /// Each session is ranked using only the history from before it.
+    Eval {
+        /// Ranking to evaluate: `frecency`, `frecency:DAYS` (another half-life),
+        /// `similarity[:DAYS]`, `buckets`, `count`, `recency` or `none`.
+        /// Repeat to compare several.
+        #[arg(short, long = "strategy", default_value = "frecency")]
+        strategies: Vec<String>,
+    },
+    /// Manage the selection and keystroke history
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+    /// Manage aliases: queries that find an emoji its keywords don't describe
+    ///
+    /// A query used to pick the same emoji several times without matching its
+    /// keywords (`alias_threshold` in the config) is learned as an alias.
+    Alias {
+        #[command(subcommand)]
+        action: AliasCommand,
+    },
+    /// Show the picker's key bindings
+    ///
+    /// The default keys, the `keymap` preset and `keybindings` from the
+    /// config; invalid and conflicting bindings are reported.
+    Keys,
+    /// Manage locale keyword files
+    Locale {
+        #[command(subcommand)]
+        action: LocaleCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// Delete recorded events (all of them unless filtered)
+    Clear {
+        /// Only delete events before this date (YYYY-MM-DD, UTC)
+        #[arg(long)]
+        before: Option<String>,
+        /// Only delete selections of this emoji (or code, e.g. `1F389`)
+        #[arg(long)]
+        emoji: Option<String>,
+    },
+    /// Fold old selections into aggregates and drop old keystrokes
+    ///
+    /// Selections are folded per day, so ranking is unaffected to within a
+    /// day. With JSONL storage, the previous file is kept
+    /// as a `.bak` backup.
+    Compact {
+        /// Days of raw history to keep (defaults to `retention_days` from the config)
+        #[arg(long)]
+        retention_days: Option<u64>,
+    },
+    /// Write the whole history as JSONL, to a file or stdout
+    Export {
+        /// File to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Merge an exported history (e.g. from another machine) into this one
+    ///
+    /// Events already present are skipped, so importing the same file twice
+    /// is harmless. Events are merged in time order.
+    Import {
+        /// JSONL history to merge
+        path: PathBuf,
+    },
+    /// Copy a JSONL history into the SQLite database, skipping events already there
+    #[cfg(feature = "sqlite")]
+    ImportJsonl {
+        /// JSONL history to import (defaults to the JSONL history file)
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AliasCommand {
+    /// List learned and added aliases
+    List,
+    /// Make a query find an emoji
+    Add {
+        /// The query, quoted if it has several words
+        alias: String,
+        /// An emoji or its code (e.g. `1F602`)
+        emoji: String,
+    },
+    /// Stop a query from finding an emoji, and from being learned again
+    Remove {
+        alias: String,
+        /// Only for this emoji (defaults to every emoji the alias finds)
+        emoji: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LocaleCommand {
+    /// Convert a CLDR annotations.json into a locale keyword file, written to stdout
+    Import {
+        /// Path to a cldr-json `annotations.json` (or `annotationsDerived`)
+        path: PathBuf,
+    },
+}
+
+pub fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Search { limit, app, query } => run_search(&query.join(" "), limit, app),
+        Command::Emojify { form } => {
+            let emoticons = Emoticons::new(&Config::load().emoticons);
+            let lexicon = load_lexicon();
+            convert::filter_stdin(|line| convert::emojify(line, &lexicon, &emoticons, form))
+                .map_err(|e| e.to_string())
+        }
+        Command::Demojify { form } => {
+            let lexicon = load_lexicon();
+            convert::filter_stdin(|line| convert::demojify(line, &lexicon, form)).map_err(|e| e.to_string())
+        }
+        Command::Related { emoji, limit } => run_related(&emoji, limit),
+        Command::Stats { limit, period, json } => run_stats(limit, period, json),
+        Command::Eval { strategies } => run_eval(&strategies),
+        Command::History { action: HistoryCommand::Clear { before, emoji } } => {
+            run_history_clear(before.as_deref(), emoji.as_deref())
+        }
+        Command::History { action: HistoryCommand::Compact { retention_days } } => {
+            let days = retention_days.unwrap_or(Config::load().retention_days);
+            let stats = open_store()?
+                .compact(days * 24 * 60 * 60, current_timestamp())
+                .map_err(|e| e.to_string())?;
+            println!(
+                "Folded {} selections into {} aggregates, dropped {} keystrokes",
+                stats.folded_selections, stats.aggregates, stats.dropped_keystrokes
+            );
+            Ok(())
+        }
+        Command::History { action: HistoryCommand::Export { output } } => run_history_export(output),
+        Command::History { action: HistoryCommand::Import { path } } => run_history_import(&path),
+        #[cfg(feature = "sqlite")]
+        Command::History { action: HistoryCommand::ImportJsonl { path } } => run_import_jsonl(path),
+        Command::Alias { action: AliasCommand::List } => run_alias_list(),
+        Command::Alias { action: AliasCommand::Add { alias, emoji } } => run_alias_add(&alias, &emoji),
+        Command::Alias { action: AliasCommand::Remove { alias, emoji } } => run_alias_remove(&alias, emoji.as_deref()),
+        Command::Keys => {
+            let config = Config::load();
+            let (keymap, replaced) = Keymap::with_replaced(&config.keymap, &config.keybindings, config.digit_select);
+            for (action, keys) in keymap.by_action() {
+                println!("{}", format!("{:<20}{}", action, keys.join(", ")).trim_end());
+            }
+            if !replaced.is_empty() {
+                println!();
+            }
+            for line in replaced {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+        Command::Locale { action: LocaleCommand::Import { path } } => {
+            for line in locale::import_cldr(&path)? {
+                println!("{}", line);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn run_search(raw: &str, limit: usize, app: Option<String>) -> Result<(), String> {
+    let input = raw.to_lowercase();
+    let config = Config::load();
+    let emoticon = Emoticons::new(&config.emoticons).get(raw.trim()).filter(|_| config.emoticon_mode).map(String::from);
+    // As in the picker, an emoticon is found even when it isn't a valid query
+    let query = match Query::parse(&input) {
+        Ok(query) => Some(query),
+        Err(_) if emoticon.is_some() => None,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut emojis = load_index(find_data_dir().as_deref(), &resolve_locales(&config));
+    let history = History::new(
+        LogLevel::Off,
+        Some(open_store()?),
+        ranker::configured(&config.ranking),
+        app,
+        config.app_weight,
+    );
+    let aliases = alias::aliases(&history.query_counts(), &emojis, &AliasEdits::load(), config.alias_threshold);
+    alias::apply(&mut emojis, &aliases);
+    let frecency = history.frecency(&input);
+
+    let mut results = query.map_or_else(Vec::new, |query| search::rank(&emojis, &query, &frecency, &Favorites::load()));
+    if let Some(record) = emoticon.and_then(|emoji| find_emoji(&emojis, &emoji)) {
+        results.retain(|m| m.code != record.code);
+        results.insert(0, record);
+    }
+
+    for entry in results.into_iter().take(limit) {
+        println!("{}\t{}\t{}", entry.emoji, entry.code, entry.description);
+    }
+    Ok(())
+}
+
+fn open_store() -> Result<Box<dyn HistoryStore>, String> {
+    store::open(Config::load().storage).map_err(|e| format!("cannot open history: {}", e))
+}
+
+fn run_alias_list() -> Result<(), String> {
+    let config = Config::load();
+    let emojis = load_index(find_data_dir().as_deref(), &resolve_locales(&config));
+    let counts = open_store()?.query_counts().map_err(|e| e.to_string())?;
+    for alias in alias::aliases(&counts, &emojis, &AliasEdits::load(), config.alias_threshold) {
+        let shown = find_code(&emojis, &alias.code).map_or("?", |r| r.emoji.as_str());
+        println!("{}\t{}\t{}\t{}", alias.alias, shown, alias.code, alias.source);
+    }
+    Ok(())
+}
+
+fn run_alias_add(alias: &str, emoji: &str) -> Result<(), String> {
+    let alias = alias::normalize(alias);
+    if alias.is_empty() {
+        return Err("the alias is empty".to_string());
+    }
+    let emojis = load_index(find_data_dir().as_deref(), &[]);
+    let record = lookup(&emojis, emoji).ok_or_else(|| format!("unknown emoji '{}'", emoji))?;
+
+    let mut edits = AliasEdits::load();
+    edits.add(&alias, &record.code);
+    edits.save().map_err(|e| format!("cannot save aliases: {}", e))?;
+    println!("\"{}\" now finds {}", alias, record.emoji);
+    Ok(())
+}
+
+fn run_alias_remove(alias: &str, emoji: Option<&str>) -> Result<(), String> {
+    let alias = alias::normalize(alias);
+    let config = Config::load();
+    let emojis = load_index(find_data_dir().as_deref(), &resolve_locales(&config));
+    let codes: Vec<String> = match emoji {
+        Some(emoji) => vec![lookup(&emojis, emoji).ok_or_else(|| format!("unknown emoji '{}'", emoji))?.code.clone()],
+        None => {
+            // Every emoji the alias finds, so learned ones stay removed
+            let counts = open_store()?.query_counts().map_err(|e| e.to_string())?;
+            alias::aliases(&counts, &emojis, &AliasEdits::load(), config.alias_threshold)
+                .into_iter()
+                .filter(|a| a.alias == alias)
+                .map(|a| a.code)
+                .collect()
+        }
+    };
+    if codes.is_empty() {
+        return Err(format!("no alias \"{}\"", alias));
+    }
+
+    let mut edits = AliasEdits::load();
+    for code in &codes {
+        edits.remove(&alias, code);
+    }
+    edits.save().map_err(|e| format!("cannot save aliases: {}", e))?;
+    println!("Removed \"{}\" for {} emoji", alias, codes.len());
+    Ok(())
+}
+
+fn load_lexicon() -> Lexicon {
+    Lexicon::new(&load_index(find_data_dir().as_deref(), &[]))
+}
+
+fn run_related(emoji: &str, limit: usize) -> Result<(), String> {
+    let emojis = load_index(find_data_dir().as_deref(), &[]);
+    let record = lookup(&emojis, emoji).ok_or_else(|| format!("unknown emoji '{}'", emoji))?;
+
+    let selections = open_store()?
+        .selections_around(&record.code, CO_SELECTION_WINDOW_SECS)
+        .map_err(|e| e.to_string())?;
+    let model = CoOccurrence::build(&selections);
+    for (code, count) in model.related(&record.code, limit) {
+        let shown = emojis.iter().find(|r| r.code == code).map_or("?", |r| r.emoji.as_str());
+        println!("{}\t{}\t{}", shown, code, count);
+    }
+    Ok(())
+}
+
+fn run_stats(limit: usize, period: Period, json: bool) -> Result<(), String> {
+    let events = open_store()?.events().map_err(|e| e.to_string())?;
+    let emojis = load_index(find_data_dir().as_deref(), &[]);
+    let report = stats::report(&events, &emojis, limit, period);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+fn run_eval(specs: &[String]) -> Result<(), String> {
+    let strategies = specs
+        .iter()
+        .map(|spec| ranker::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let config = Config::load();
+    let events = open_store()?.events().map_err(|e| e.to_string())?;
+    let emojis = load_index(find_data_dir().as_deref(), &resolve_locales(&config));
+    let emoticons = Emoticons::new(&config.emoticons);
+    let emoticons = config.emoticon_mode.then_some(&emoticons);
+
+    println!("{}", eval::HEADER);
+    for strategy in &strategies {
+        println!("{}", eval::evaluate(&events, &emojis, emoticons, strategy.as_ref()));
+    }
+    Ok(())
+}
+
+fn run_history_clear(before: Option<&str>, emoji: Option<&str>) -> Result<(), String> {
+    let before = before.map(parse_date).transpose()?;
+    let code = match emoji {
+        Some(emoji) => {
+            let emojis = load_index(find_data_dir().as_deref(), &[]);
+            let record = lookup(&emojis, emoji).ok_or_else(|| format!("unknown emoji '{}'", emoji))?;
+            Some(record.code.clone())
+        }
+        None => None,
+    };
+
+    let store = open_store()?;
+    let removed = store.clear(&ClearFilter { before, code }).map_err(|e| e.to_string())?;
+    println!("Removed {} events from {}", removed, store.location());
+    Ok(())
+}
+
+fn run_history_export(output: Option<PathBuf>) -> Result<(), String> {
+    let events = open_store()?.events().map_err(|e| e.to_string())?;
+    let content: String = events.iter().filter_map(LogEvent::to_line).collect();
+
+    match output {
+        Some(path) => {
+            fs::write(&path, content).map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+            eprintln!("Exported {} events to {}", events.len(), path.display());
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+fn run_history_import(path: &Path) -> Result<(), String> {
+    let mut events = Vec::new();
+    let skipped = read_events(path, |event| events.push(event))
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let total = events.len();
+
+    let store = open_store()?;
+    let added = store.import(events).map_err(|e| e.to_string())?;
+    println!(
+        "Imported {} new events into {} ({} already present)",
+        added,
+        store.location(),
+        total - added
+    );
+    if skipped.total() > 0 {
+        println!("Skipped {}", skipped);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn run_import_jsonl(path: Option<PathBuf>) -> Result<(), String> {
+    let source = path
+        .or_else(crate::history::get_log_path)
+        .ok_or("no JSONL history to import")?;
+    let target = crate::history::get_log_path()
+        .map(|p| store::sqlite_path(&p))
+        .ok_or("no location for the history")?;
+    let store = crate::sqlite::SqliteStore::open(&target).map_err(|e| e.to_string())?;
+
+    let (added, skipped) = store.import_jsonl(&source).map_err(|e| e.to_string())?;
+    println!("Imported {} events from {} into {}", added, source.display(), target.display());
+    if skipped.total() > 0 {
+        println!("Skipped {}", skipped);
+    }
+    Ok(())
+}
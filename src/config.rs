@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::active_app::AppDetection;
+use crate::history::LogLevel;
+use crate::instance::SingleInstance;
+use crate::placement::Placement;
+use crate::store::Storage;
+
+/// User configuration, read from `~/.emoru/config.json`.
+/// Every field is optional; missing fields fall back to their defaults.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Locales to search, e.g. `["de", "en"]`. The first locale with a
+    /// description for an emoji is the one displayed. When empty, the
+    /// locale is taken from the environment (`LANGUAGE`, `LC_ALL`, `LANG`).
+    pub locales: Vec<String>,
+    /// When the query is exactly an emoticon such as `:)` or `<3`,
+    /// put its emoji first in the results
+    pub emoticon_mode: bool,
+    /// Additional emoticons, mapping text to emoji (e.g. `{"(y)": "👍"}`)
+    pub emoticons: HashMap<String, String>,
+    /// What the history records: `"off"`, `"selections"` or `"full"` (keystrokes too)
+    pub logging: LogLevel,
+    /// Days of raw history to keep; older selections are compacted into
+    /// aggregates and older keystrokes are dropped
+    pub retention_days: u64,
+    /// Path of the history file, instead of `$XDG_STATE_HOME/emoru/history.jsonl`.
+    /// The `EMORU_HISTORY` environment variable takes precedence.
+    pub history_file: Option<PathBuf>,
+    /// A directory synced between machines (e.g. with Syncthing or Dropbox).
+    /// Each machine writes its own `<hostname>.jsonl` there and reads all of them.
+    pub shared_history_dir: Option<PathBuf>,
+    /// History backend: `"jsonl"` or `"sqlite"` (when built with the `sqlite` feature)
+    pub storage: Storage,
+    /// How to find the application the picker is opened from: `"auto"`,
+    /// `"x11"`, `"macos"`, `{"command": "..."}` or `"none"`
+    pub app_detection: AppDetection,
+    /// How much selections made in the same application count, from 0
+    /// (global ranking only) to 1 (per-application ranking only)
+    pub app_weight: f64,
+    /// Selections of an emoji by the same query, when its keywords don't
+    /// match that query, after which the query becomes an alias for it.
+    /// 0 turns learning off.
+    pub alias_threshold: u32,
+    /// How past selections rank emoji: `"frecency"` (exponential decay, 7-day
+    /// half-life), `"frecency:DAYS"`, `"similarity[:DAYS]"` (closer queries
+    /// count more), `"buckets"`, `"count"`, `"recency"` or `"none"`
+    pub ranking: String,
+    /// `"auto"` (light or dark, following the desktop), `"light"`, `"dark"`,
+    /// `"high-contrast"`, or the name of a theme in `~/.emoru/themes/<name>.json`
+    pub theme: String,
+    /// Show the details panel for the highlighted emoji (toggled with Alt+I)
+    pub preview: bool,
+    /// Key preset: `"default"`, `"vim"` (Ctrl+J/K to move) or `"emacs"` (Ctrl+N/P)
+    pub keymap: String,
+    /// Keys bound to actions on top of the preset, e.g. `{"ctrl-l": "clear"}`;
+    /// `"none"` unbinds a key
+    pub keybindings: BTreeMap<String, String>,
+    /// Pick the Nth visible result with plain digits as well as Alt+1..9.
+    /// Digits can then no longer be typed into the search.
+    pub digit_select: bool,
+    /// Where the picker opens: `"center"` (of the monitor with the pointer),
+    /// `"pointer"`, `"caret"` (below the text caret, else at the pointer) or
+    /// `"system"` (left to the window manager)
+    pub placement: Placement,
+    /// Close the picker when another window is activated
+    pub close_on_focus_loss: bool,
+    /// What launching the picker while it is open does: `"toggle"` (close
+    /// it), `"focus"` (bring it to the front) or `"off"` (open another one)
+    pub single_instance: SingleInstance,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            locales: Vec::new(),
+            emoticon_mode: true,
+            emoticons: HashMap::new(),
+            logging: LogLevel::default(),
+            retention_days: 90,
+            history_file: None,
+            shared_history_dir: None,
+            storage: Storage::default(),
+            app_detection: AppDetection::default(),
+            app_weight: 0.5,
+            alias_threshold: 3,
+            ranking: "frecency".to_string(),
+            theme: "auto".to_string(),
+            preview: false,
+            keymap: "default".to_string(),
+            keybindings: BTreeMap::new(),
+            digit_select: false,
+            placement: Placement::default(),
+            close_on_focus_loss: true,
+            single_instance: SingleInstance::default(),
+        }
+    }
+}
+
+pub fn get_config_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(".emoru"))
+}
+
+/// Read a JSON file kept in the config directory, such as the favorites,
+/// falling back to the default if it is missing or invalid
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> T {
+    let Some(path) = get_config_dir().map(|d| d.join(name)) else {
+        return T::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("emoru: ignoring invalid {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Write a JSON file in the config directory
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> io::Result<()> {
+    let dir = get_config_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    fs::create_dir_all(&dir)?;
+    let content = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    // Write a temporary file and rename, so a crash never leaves half a file
+    let path = dir.join(name);
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, content + "\n")?;
+    fs::rename(&tmp_path, &path)
+}
+
+/// Change one setting in `~/.emoru/config.json`, keeping the others as written.
+/// An invalid config file is left alone.
+pub fn save_setting(key: &str, value: serde_json::Value) -> io::Result<()> {
+    let path = get_config_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?
+        .join("config.json");
+    let mut settings = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::Value::Object(Default::default()),
+        Err(e) => return Err(e),
+    };
+    let Some(object) = settings.as_object_mut() else {
+        return Err(io::Error::other(format!("{} is not a JSON object", path.display())));
+    };
+    object.insert(key.to_string(), value);
+    save_json("config.json", &settings)
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults if it is missing or invalid
+    pub fn load() -> Self {
+        let Some(path) = get_config_dir().map(|d| d.join("config.json")) else {
+            return Self::default();
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("emoru: ignoring invalid config {}: {}", path.display(), e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+}
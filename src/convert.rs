@@ -0,0 +1,414 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+use clap::ValueEnum;
+
+use crate::emoticon::Emoticons;
+use crate::index::EmojiRecord;
+use crate::locale::emoji_to_code;
+
+/// Textual notation for an emoji, as written by `demojify`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Form {
+    /// `:tada:`
+    Shortcode,
+    /// `[party popper]`
+    Name,
+    /// `U+1F389` (sequences as `U+1F469_200D_1F680`)
+    Codepoint,
+    /// `&#x1F389;`
+    Html,
+}
+
+/// Every known emoji sequence, with shortcode and name lookups, built from
+/// the search index plus Unicode metadata (which adds skin tone variants)
+pub struct Lexicon {
+    sequences: HashSet<String>,
+    max_len: usize,
+    by_shortcode: HashMap<String, &'static emojis::Emoji>,
+    by_name: HashMap<String, &'static emojis::Emoji>,
+}
+
+impl Lexicon {
+    pub fn new(index: &[EmojiRecord]) -> Self {
+        let mut lexicon = Self {
+            sequences: HashSet::new(),
+            max_len: 0,
+            by_shortcode: HashMap::new(),
+            by_name: HashMap::new(),
+        };
+
+        for record in index {
+            lexicon.add_sequence(&record.emoji);
+        }
+
+        let all: Vec<&'static emojis::Emoji> = emojis::iter()
+            .flat_map(|base| match base.skin_tones() {
+                Some(tones) => tones.collect(),
+                None => vec![base],
+            })
+            .collect();
+
+        for &emoji in &all {
+            lexicon.add_sequence(emoji.as_str());
+            lexicon.by_name.insert(emoji.name().to_lowercase(), emoji);
+            for shortcode in emoji.shortcodes() {
+                lexicon.by_shortcode.entry(shortcode.to_string()).or_insert(emoji);
+            }
+        }
+
+        // Emoji without a GitHub shortcode get one derived from their name;
+        // real shortcodes take precedence on collision
+        for &emoji in &all {
+            lexicon.by_shortcode.entry(name_to_shortcode(emoji.name())).or_insert(emoji);
+        }
+
+        lexicon
+    }
+
+    /// Register a sequence both as given and without variation selectors
+    fn add_sequence(&mut self, sequence: &str) {
+        for variant in [sequence.to_string(), sequence.replace('\u{FE0F}', "")] {
+            self.max_len = self.max_len.max(variant.chars().count());
+            self.sequences.insert(variant);
+        }
+    }
+
+    /// Length in chars of the longest known emoji sequence starting at `chars[0]`
+    fn longest_match(&self, chars: &[char]) -> Option<usize> {
+        (1..=self.max_len.min(chars.len()))
+            .rev()
+            .find(|&n| self.sequences.contains(&chars[..n].iter().collect::<String>()))
+    }
+
+    /// Whether `text` consists entirely of known emoji sequences
+    fn is_emoji_text(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match self.longest_match(&chars[i..]) {
+                Some(n) => i += n,
+                None => return false,
+            }
+        }
+        !chars.is_empty()
+    }
+
+    fn shortcode_for(&self, emoji: &'static emojis::Emoji) -> String {
+        emoji
+            .shortcode()
+            .map(String::from)
+            .unwrap_or_else(|| name_to_shortcode(emoji.name()))
+    }
+
+    /// Write an emoji sequence in the given notation. Returns None when the
+    /// notation would not decode back to the same emoji, so the round trip
+    /// through `emojify` always holds.
+    fn encode(&self, sequence: &str, form: Form) -> Option<String> {
+        match form {
+            Form::Codepoint => Some(format!("U+{}", emoji_to_code(sequence))),
+            Form::Html => Some(sequence.chars().map(|c| format!("&#x{:X};", c as u32)).collect()),
+            Form::Shortcode => {
+                let emoji = emojis::get(sequence)?;
+                let shortcode = self.shortcode_for(emoji);
+                (self.by_shortcode.get(&shortcode) == Some(&emoji)).then(|| format!(":{}:", shortcode))
+            }
+            Form::Name => {
+                let emoji = emojis::get(sequence)?;
+                let name = emoji.name();
+                (!name.contains(']') && self.by_name.get(&name.to_lowercase()) == Some(&emoji))
+                    .then(|| format!("[{}]", name))
+            }
+        }
+    }
+}
+
+/// Derive a shortcode from an emoji name: "waving hand: medium skin tone" -> `waving_hand_medium_skin_tone`
+fn name_to_shortcode(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Replace every emoji in a line of text with the given notation.
+/// Sequences (ZWJ, skin tones, keycaps, flags) are matched longest-first.
+/// `emojify --form <form>` restores the emoji, up to U+FE0F presentation
+/// selectors for the shortcode and name forms.
+pub fn demojify(text: &str, lexicon: &Lexicon, form: Form) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match lexicon.longest_match(&chars[i..]) {
+            Some(n) => {
+                let sequence: String = chars[i..i + n].iter().collect();
+                match lexicon.encode(&sequence, form) {
+                    Some(replacement) => out.push_str(&replacement),
+                    None => out.push_str(&sequence),
+                }
+                i += n;
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// Replace emoticons and `:shortcodes:` in a line of text with emoji.
+/// Emoticons are only replaced when they form a whole whitespace-delimited
+/// token, so URLs like `http://` are left alone. With `decode`, only the
+/// given `demojify` notation is converted back, so emoticons and shortcodes
+/// that were in the text before `demojify` survive the round trip.
+pub fn emojify(text: &str, lexicon: &Lexicon, emoticons: &Emoticons, decode: Option<Form>) -> String {
+    match decode {
+        Some(Form::Shortcode) => return replace_shortcodes(text, lexicon),
+        Some(Form::Name) => return decode_names(text, lexicon),
+        Some(Form::Codepoint) => return decode_codepoints(text, lexicon),
+        Some(Form::Html) => return decode_html(text, lexicon),
+        None => {}
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut token = String::new();
+
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            flush_token(&mut token, &mut out, lexicon, emoticons);
+            out.push(ch);
+        } else {
+            token.push(ch);
+        }
+    }
+    flush_token(&mut token, &mut out, lexicon, emoticons);
+
+    out
+}
+
+fn flush_token(token: &mut String, out: &mut String, lexicon: &Lexicon, emoticons: &Emoticons) {
+    if token.is_empty() {
+        return;
+    }
+    match emoticons.get(token) {
+        Some(emoji) => out.push_str(emoji),
+        None => out.push_str(&replace_shortcodes(token, lexicon)),
+    }
+    token.clear();
+}
+
+/// Replace every `:name:` whose name is a known shortcode
+fn replace_shortcodes(text: &str, lexicon: &Lexicon) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(':') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let candidate = after
+            .find(':')
+            .map(|end| &after[..end])
+            .filter(|name| is_shortcode_name(name))
+            .and_then(|name| lexicon.by_shortcode.get(name).map(|e| (name, e)));
+
+        match candidate {
+            Some((name, emoji)) => {
+                out.push_str(emoji.as_str());
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn is_shortcode_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+}
+
+/// Replace `[emoji name]` with the emoji
+fn decode_names(text: &str, lexicon: &Lexicon) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let candidate = after
+            .find(']')
+            .map(|end| &after[..end])
+            .and_then(|name| lexicon.by_name.get(&name.to_lowercase()).map(|e| (name, e)));
+
+        match candidate {
+            Some((name, emoji)) => {
+                out.push_str(emoji.as_str());
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                out.push('[');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Replace `U+1F389` / `U+1F469_200D_1F680` when they spell a known emoji.
+/// The form has no terminator, so the longest run of hex digits that decodes
+/// to emoji wins and the rest stays text: `U+1F3892024` is `🎉2024`.
+fn decode_codepoints(text: &str, lexicon: &Lexicon) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("U+") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find(|c: char| !(c.is_ascii_hexdigit() || c == '_'))
+            .unwrap_or(after.len());
+        let decoded = (1..=end)
+            .rev()
+            .filter(|&len| !after[..len].ends_with('_'))
+            .find_map(|len| decode_code(&after[..len]).filter(|s| lexicon.is_emoji_text(s)).map(|s| (len, s)));
+
+        match decoded {
+            Some((len, sequence)) => {
+                out.push_str(&sequence);
+                rest = &after[len..];
+            }
+            None => {
+                out.push_str("U+");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Parse `1F469_200D_1F680` into the characters it names
+fn decode_code(code: &str) -> Option<String> {
+    code.split('_')
+        .map(|hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32))
+        .collect()
+}
+
+/// Replace runs of `&#x1F389;` / `&#127881;` entities when they spell known emoji
+fn decode_html(text: &str, lexicon: &Lexicon) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("&#") {
+        out.push_str(&rest[..start]);
+        let run = &rest[start..];
+
+        // Collect consecutive entities
+        let mut decoded = String::new();
+        let mut consumed = 0;
+        while let Some((ch, len)) = parse_entity(&run[consumed..]) {
+            decoded.push(ch);
+            consumed += len;
+        }
+
+        if consumed > 0 && lexicon.is_emoji_text(&decoded) {
+            out.push_str(&decoded);
+            rest = &run[consumed..];
+        } else {
+            out.push_str("&#");
+            rest = &run[2..];
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Parse one `&#x...;` or `&#...;` entity at the start of `text`
+fn parse_entity(text: &str) -> Option<(char, usize)> {
+    let body = text.strip_prefix("&#")?;
+    let end = body.find(';')?;
+    let value = &body[..end];
+    let codepoint = match value.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => value.parse().ok()?,
+    };
+    Some((char::from_u32(codepoint)?, end + 3))
+}
+
+/// Run a line-by-line filter from stdin to stdout, preserving line endings
+pub fn filter_stdin(convert: impl Fn(&str) -> String) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = io::BufWriter::new(stdout.lock());
+    let mut line = String::new();
+
+    while input.read_line(&mut line)? > 0 {
+        let (body, ending) = match line.strip_suffix("\r\n") {
+            Some(body) => (body, "\r\n"),
+            None => match line.strip_suffix('\n') {
+                Some(body) => (body, "\n"),
+                None => (line.as_str(), ""),
+            },
+        };
+        write!(output, "{}{}", convert(body), ending)?;
+        line.clear();
+    }
+
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn round_trip(text: &str, form: Form) -> String {
+        let lexicon = Lexicon::new(&[]);
+        let emoticons = Emoticons::new(&HashMap::new());
+        emojify(&demojify(text, &lexicon, form), &lexicon, &emoticons, Some(form))
+    }
+
+    #[test]
+    fn encodes_each_form() {
+        let lexicon = Lexicon::new(&[]);
+        assert_eq!(demojify("hi 🎉", &lexicon, Form::Shortcode), "hi :tada:");
+        assert_eq!(demojify("hi 🎉", &lexicon, Form::Name), "hi [party popper]");
+        assert_eq!(demojify("👩‍🚀!", &lexicon, Form::Codepoint), "U+1F469_200D_1F680!");
+        assert_eq!(demojify("🎉", &lexicon, Form::Html), "&#x1F389;");
+    }
+
+    #[test]
+    fn round_trip_keeps_emoticons_and_shortcodes() {
+        let text = "I :) you 🎉 :tada: <3 👩‍🚀👍🏽 🎉2024 👍bad";
+        for form in [Form::Name, Form::Codepoint, Form::Html] {
+            assert_eq!(round_trip(text, form), text);
+        }
+        // Shortcodes already in the text can't be told apart from encoded ones
+        assert_eq!(round_trip("I :) you 🎉 <3 👩‍🚀👍🏽", Form::Shortcode), "I :) you 🎉 <3 👩‍🚀👍🏽");
+    }
+
+    #[test]
+    fn emojify_converts_emoticons_and_shortcodes() {
+        let lexicon = Lexicon::new(&[]);
+        let emoticons = Emoticons::new(&HashMap::new());
+        assert_eq!(emojify("I :) you :tada: http://x", &lexicon, &emoticons, None), "I 🙂 you 🎉 http://x");
+    }
+}
@@ -0,0 +1,210 @@
+/// The search query being typed, with a cursor and readline-style editing.
+/// Words are separated by whitespace, like query terms.
+#[derive(Default)]
+pub struct LineEditor {
+    chars: Vec<char>,
+    /// Position of the cursor, from 0 (before the first char) to `chars.len()`
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// The text before and after the cursor
+    pub fn split(&self) -> (String, String) {
+        (self.chars[..self.cursor].iter().collect(), self.chars[self.cursor..].iter().collect())
+    }
+
+    /// Insert text at the cursor. Line breaks become spaces, so a pasted
+    /// multi-line text stays one query.
+    pub fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            let c = if c == '\n' || c == '\r' || c == '\t' { ' ' } else { c };
+            if !c.is_control() {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.clear();
+        self.cursor = 0;
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    /// Start of the word before the cursor
+    fn word_start(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos > 0 && self.chars[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        while pos > 0 && !self.chars[pos - 1].is_whitespace() {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// End of the word after the cursor
+    fn word_end(&self) -> usize {
+        let mut pos = self.cursor;
+        while pos < self.chars.len() && self.chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        while pos < self.chars.len() && !self.chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        pos
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    pub fn word_right(&mut self) {
+        self.cursor = self.word_end();
+    }
+
+    /// Delete the char before the cursor (Backspace)
+    pub fn delete_back(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Delete the char under the cursor (Delete)
+    pub fn delete_forward(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Delete the word before the cursor (Ctrl+W, Alt+Backspace)
+    pub fn delete_word_back(&mut self) {
+        let start = self.word_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Delete the word after the cursor (Alt+D)
+    pub fn delete_word_forward(&mut self) {
+        let end = self.word_end();
+        self.chars.drain(self.cursor..end);
+    }
+
+    /// Delete everything before the cursor (Ctrl+U)
+    pub fn kill_to_start(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Delete everything after the cursor (Ctrl+K)
+    pub fn kill_to_end(&mut self) {
+        self.chars.truncate(self.cursor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An editor holding `before` and `after` with the cursor between them
+    fn editor(before: &str, after: &str) -> LineEditor {
+        let mut editor = LineEditor::default();
+        editor.insert(after);
+        editor.home();
+        editor.insert(before);
+        editor
+    }
+
+    fn pair(before: &str, after: &str) -> (String, String) {
+        (before.to_string(), after.to_string())
+    }
+
+    #[test]
+    fn inserts_at_the_cursor() {
+        let mut e = editor("red ", "heart");
+        e.insert("big\nfat\t");
+        assert_eq!(e.split(), pair("red big fat ", "heart"));
+        e.insert("\u{7}");
+        assert_eq!(e.text(), "red big fat heart");
+    }
+
+    #[test]
+    fn moves_by_char_and_line() {
+        let mut e = editor("ab", "c");
+        e.right();
+        e.right();
+        assert_eq!(e.split(), pair("abc", ""));
+        e.home();
+        e.left();
+        assert_eq!(e.split(), pair("", "abc"));
+        e.end();
+        assert_eq!(e.split(), pair("abc", ""));
+    }
+
+    #[test]
+    fn moves_by_word() {
+        let mut e = editor("red  heart", " eyes");
+        e.word_left();
+        assert_eq!(e.split(), pair("red  ", "heart eyes"));
+        e.word_left();
+        assert_eq!(e.split(), pair("", "red  heart eyes"));
+        e.word_right();
+        assert_eq!(e.split(), pair("red", "  heart eyes"));
+        e.word_right();
+        assert_eq!(e.split(), pair("red  heart", " eyes"));
+    }
+
+    #[test]
+    fn deletes_chars_and_words() {
+        let mut e = editor("smiling fa", "ce cat");
+        e.delete_back();
+        e.delete_forward();
+        assert_eq!(e.split(), pair("smiling f", "e cat"));
+        e.delete_word_back();
+        assert_eq!(e.split(), pair("smiling ", "e cat"));
+        e.delete_word_forward();
+        assert_eq!(e.split(), pair("smiling ", " cat"));
+
+        let mut e = editor("", "x");
+        e.delete_back();
+        e.end();
+        e.delete_forward();
+        assert_eq!(e.text(), "x");
+    }
+
+    #[test]
+    fn kills_to_either_end() {
+        let mut e = editor("grinning ", "face");
+        e.kill_to_end();
+        assert_eq!(e.split(), pair("grinning ", ""));
+        e.left();
+        e.kill_to_start();
+        assert_eq!(e.split(), pair("", " "));
+        e.clear();
+        assert!(e.is_empty());
+    }
+}
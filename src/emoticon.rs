@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+/// Built-in ASCII emoticons and the emoji they stand for
+const EMOTICONS: &[(&str, &str)] = &[
+    (":)", "🙂"),
+    (":-)", "🙂"),
+    ("(:", "🙂"),
+    (":]", "🙂"),
+    ("=)", "🙂"),
+    (":D", "😀"),
+    (":-D", "😀"),
+    ("=D", "😀"),
+    ("xD", "😆"),
+    ("XD", "😆"),
+    (";)", "😉"),
+    (";-)", "😉"),
+    (":(", "🙁"),
+    (":-(", "🙁"),
+    ("):", "🙁"),
+    (":'(", "😢"),
+    (":'-(", "😢"),
+    (":'D", "😂"),
+    (":P", "😛"),
+    (":p", "😛"),
+    (":-P", "😛"),
+    (":-p", "😛"),
+    (";P", "😜"),
+    (";p", "😜"),
+    (":O", "😮"),
+    (":o", "😮"),
+    (":-O", "😮"),
+    (":-o", "😮"),
+    (":/", "😕"),
+    (":-/", "😕"),
+    (":\\", "😕"),
+    (":|", "😐"),
+    (":-|", "😐"),
+    ("-_-", "😑"),
+    (":*", "😘"),
+    (":-*", "😘"),
+    ("B)", "😎"),
+    ("B-)", "😎"),
+    ("8)", "😎"),
+    ("^_^", "😊"),
+    ("^^", "😊"),
+    (">:(", "😠"),
+    (">:-(", "😠"),
+    ("D:", "😧"),
+    (":$", "😳"),
+    ("O:)", "😇"),
+    ("O:-)", "😇"),
+    (">:)", "😈"),
+    (">:-)", "😈"),
+    ("<3", "❤️"),
+    ("</3", "💔"),
+    ("o/", "👋"),
+    ("\\o", "👋"),
+    ("\\o/", "🙌"),
+    ("¯\\_(ツ)_/¯", "🤷"),
+];
+
+/// Emoticon lookup table: the built-in set extended by `emoticons` from the config
+pub struct Emoticons {
+    map: HashMap<String, String>,
+}
+
+impl Emoticons {
+    pub fn new(extra: &HashMap<String, String>) -> Self {
+        let mut map: HashMap<String, String> = EMOTICONS
+            .iter()
+            .map(|(text, emoji)| (text.to_string(), emoji.to_string()))
+            .collect();
+        map.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Self { map }
+    }
+
+    /// The emoji for an emoticon, if `text` is exactly a known emoticon
+    pub fn get(&self, text: &str) -> Option<&str> {
+        self.map.get(text).map(String::as_str)
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::emoticon::Emoticons;
+use crate::favorites::Favorites;
+use crate::history::{LogEvent, Selection};
+use crate::index::{find_code, find_emoji, EmojiRecord};
+use crate::locale::normalize_code;
+use crate::query::Query;
+use crate::ranker::Ranker;
+use crate::search;
+use crate::session::sessions;
+use crate::NUM_SLOTS;
+
+/// How well a ranker would have ranked the recorded selections
+pub struct Evaluation {
+    pub strategy: String,
+    pub sessions: usize,
+    /// Mean reciprocal rank of the selected emoji for the final query
+    pub mrr: f64,
+    /// Fraction of sessions where the selected emoji ranked first
+    pub top1: f64,
+    /// Keys pressed in the session minus the fewest needed with this strategy
+    /// (typing a prefix of the query, then arrowing down), on average
+    pub keystrokes_saved: f64,
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>8} {:>8.3} {:>7.1}% {:>+10.2}",
+            self.strategy,
+            self.sessions,
+            self.mrr,
+            self.top1 * 100.0,
+            self.keystrokes_saved
+        )
+    }
+}
+
+pub const HEADER: &str = "strategy         sessions      MRR   top-1  keys saved";
+
+/// Replay every recorded session against a ranker, using only the history
+/// from before each session. Sessions whose emoji is not in the index are skipped.
+pub fn evaluate(
+    events: &[LogEvent],
+    emojis: &[EmojiRecord],
+    emoticons: Option<&Emoticons>,
+    ranker: &dyn Ranker,
+) -> Evaluation {
+    // Events are in time order, so the history before a session is a prefix
+    let selections: Vec<Selection> = events.iter().cloned().filter_map(Selection::from_event).collect();
+    let mut evaluated = 0;
+    let mut reciprocal_ranks = 0.0;
+    let mut top1 = 0;
+    let mut saved = 0.0;
+
+    for session in sessions(events) {
+        let prior = &selections[..selections.partition_point(|s| s.ts < session.start)];
+        let Some(target) = find_code(emojis, &session.code) else {
+            continue;
+        };
+        let target = normalize_code(&target.code);
+
+        let rank_for = |query: &str| -> Option<usize> {
+            let parsed = Query::parse(&query.to_lowercase()).ok()?;
+            let scores = ranker.scores(prior, &query.to_lowercase(), None, session.ts);
+            let emoticon = emoticons
+                .and_then(|e| e.get(query.trim()))
+                .and_then(|emoji| find_emoji(emojis, emoji));
+            search::results(emojis, &parsed, &scores, &Favorites::default(), emoticon)
+                .iter()
+                .position(|r| normalize_code(&r.code) == target)
+                .map(|i| i + 1)
+        };
+
+        let query: Vec<char> = session.query.chars().collect();
+        let rank = rank_for(&session.query);
+        evaluated += 1;
+        if let Some(rank) = rank {
+            reciprocal_ranks += 1.0 / rank as f64;
+            if rank == 1 {
+                top1 += 1;
+            }
+        }
+
+        // Fewest keys: type some prefix, then press Down until the emoji is highlighted
+        let fewest = (0..=query.len())
+            .filter_map(|n| {
+                let prefix: String = query[..n].iter().collect();
+                rank_for(&prefix)
+                    .filter(|&r| r <= NUM_SLOTS)
+                    .map(|r| n + r - 1)
+            })
+            .min()
+            .unwrap_or(query.len());
+        let pressed = if session.has_keystrokes() { session.keys.len() } else { query.len() };
+        saved += pressed as f64 - fewest as f64;
+    }
+
+    let sessions = evaluated.max(1) as f64;
+    Evaluation {
+        strategy: ranker.name(),
+        sessions: evaluated,
+        mrr: reciprocal_ranks / sessions,
+        top1: top1 as f64 / sessions,
+        keystrokes_saved: saved / sessions,
+    }
+}
@@ -0,0 +1,78 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{load_json, save_json};
+use crate::locale::normalize_code;
+
+/// Pinned and hidden emoji, kept in `~/.emoru/favorites.json` as lists of
+/// emoji codes (`1F44D`). Pinned emoji lead the empty-query view in pin
+/// order; hidden emoji only appear when queried exactly.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Favorites {
+    pub pinned: Vec<String>,
+    pub hidden: Vec<String>,
+}
+
+const FAVORITES_FILE: &str = "favorites.json";
+
+fn contains(list: &[String], code: &str) -> bool {
+    let code = normalize_code(code);
+    list.iter().any(|c| normalize_code(c) == code)
+}
+
+/// Add `code` to `list`, or remove it if present. Returns whether it is now in the list.
+fn toggle(list: &mut Vec<String>, code: &str) -> bool {
+    if contains(list, code) {
+        let code = normalize_code(code);
+        list.retain(|c| normalize_code(c) != code);
+        false
+    } else {
+        list.push(code.to_string());
+        true
+    }
+}
+
+impl Favorites {
+    /// Load the favorites file, falling back to empty lists if it is missing or invalid
+    pub fn load() -> Self {
+        load_json(FAVORITES_FILE)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        save_json(FAVORITES_FILE, self)
+    }
+
+    pub fn is_pinned(&self, code: &str) -> bool {
+        self.pin_position(code).is_some()
+    }
+
+    pub fn is_hidden(&self, code: &str) -> bool {
+        contains(&self.hidden, code)
+    }
+
+    /// Pin or unpin an emoji. Pinning also unhides it. Returns whether it is now pinned.
+    pub fn toggle_pin(&mut self, code: &str) -> bool {
+        let pinned = toggle(&mut self.pinned, code);
+        if pinned && self.is_hidden(code) {
+            toggle(&mut self.hidden, code);
+        }
+        pinned
+    }
+
+    /// Hide or unhide an emoji. Hiding also unpins it. Returns whether it is now hidden.
+    pub fn toggle_hide(&mut self, code: &str) -> bool {
+        let hidden = toggle(&mut self.hidden, code);
+        if hidden && self.is_pinned(code) {
+            toggle(&mut self.pinned, code);
+        }
+        hidden
+    }
+
+    /// Position of a pinned emoji in the pin list
+    pub fn pin_position(&self, code: &str) -> Option<usize> {
+        let code = normalize_code(code);
+        self.pinned.iter().position(|c| normalize_code(c) == code)
+    }
+}
@@ -0,0 +1,877 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ranker::Ranker;
+use crate::related::{CoOccurrence, CO_SELECTION_WINDOW_SECS};
+use crate::store::{blend_frecency, HistoryStore, FRECENCY_HALF_LIFE_SECS};
+
+/// Version of the event schema written to the history. Each line carries it
+/// as `"v"`; lines without it are from before versioning and read as-is.
+/// Unknown fields are ignored, so a newer version that only adds fields can
+/// still be read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LogEvent {
+    #[serde(rename = "keystroke")]
+    Keystroke { ts: u64, key: String },
+    /// `app` is the application that was active when the picker opened, if known
+    #[serde(rename = "select")]
+    Select {
+        ts: u64,
+        code: String,
+        query: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        app: Option<String>,
+    },
+    /// Selections folded by compaction, all made on the same day and the
+    /// last of them at `ts`: `weight` is their summed frecency decay as of
+    /// `ts`, so every ranker scores them as it would the raw events, to
+    /// within a day
+    #[serde(rename = "aggregate")]
+    Aggregate {
+        ts: u64,
+        code: String,
+        query: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        app: Option<String>,
+        weight: f64,
+        count: u32,
+    },
+}
+
+impl LogEvent {
+    pub fn ts(&self) -> u64 {
+        match self {
+            LogEvent::Keystroke { ts, .. } | LogEvent::Select { ts, .. } | LogEvent::Aggregate { ts, .. } => *ts,
+        }
+    }
+
+    /// Serialize as one history line, including the schema version
+    pub fn to_line(&self) -> Option<String> {
+        #[derive(Serialize)]
+        struct Versioned<'a> {
+            v: u32,
+            #[serde(flatten)]
+            event: &'a LogEvent,
+        }
+
+        let mut line = serde_json::to_string(&Versioned { v: SCHEMA_VERSION, event: self }).ok()?;
+        line.push('\n');
+        Some(line)
+    }
+
+    /// Identity used to recognize an event that is already in a history.
+    /// Queries are compared case-insensitively, as they are ranked.
+    fn dedup_key(&self) -> (u64, &'static str, String, String, Option<String>) {
+        match self {
+            LogEvent::Keystroke { ts, key } => (*ts, "keystroke", key.clone(), String::new(), None),
+            LogEvent::Select { ts, code, query, app } => {
+                (*ts, "select", code.clone(), query.to_lowercase(), app.clone())
+            }
+            LogEvent::Aggregate { ts, code, query, app, .. } => {
+                (*ts, "aggregate", code.clone(), query.to_lowercase(), app.clone())
+            }
+        }
+    }
+
+    /// Parse a history line, explaining why it cannot be used if it fails
+    fn from_line(line: &str) -> Result<Self, SkipReason> {
+        serde_json::from_str(line).map_err(|_| {
+            let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(line) else {
+                return SkipReason::Malformed;
+            };
+            let version = fields.get("v").and_then(|v| v.as_u64()).unwrap_or(0);
+            let known_type = matches!(
+                fields.get("type").and_then(|t| t.as_str()),
+                Some("keystroke" | "select" | "aggregate")
+            );
+            if version > u64::from(SCHEMA_VERSION) {
+                SkipReason::NewerVersion
+            } else if !known_type {
+                SkipReason::UnknownEvent
+            } else {
+                SkipReason::Malformed
+            }
+        })
+    }
+}
+
+/// Why a history line was skipped when reading
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// Not valid JSON, or an event with missing or mistyped fields
+    Malformed,
+    /// Valid JSON with an event type this version does not know
+    UnknownEvent,
+    /// Written by a newer version with an incompatible schema
+    NewerVersion,
+}
+
+impl SkipReason {
+    fn describe(self) -> &'static str {
+        match self {
+            SkipReason::Malformed => "malformed",
+            SkipReason::UnknownEvent => "unknown event type",
+            SkipReason::NewerVersion => "newer schema version",
+        }
+    }
+}
+
+/// Counts of history lines skipped when reading, by reason
+#[derive(Default)]
+pub struct Skipped(BTreeMap<SkipReason, usize>);
+
+impl Skipped {
+    fn add(&mut self, reason: SkipReason) {
+        *self.0.entry(reason).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reasons: Vec<String> = self
+            .0
+            .iter()
+            .map(|(reason, count)| format!("{} {}", count, reason.describe()))
+            .collect();
+        write!(f, "{} lines ({})", self.total(), reasons.join(", "))
+    }
+}
+
+/// How much of a picker session is written to the history
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Record nothing
+    Off,
+    /// Record selected emoji with the query that found them
+    Selections,
+    /// Also record every keystroke
+    #[default]
+    Full,
+}
+
+/// Environment variable pointing the history at a specific file, e.g. for tests
+pub const HISTORY_ENV: &str = "EMORU_HISTORY";
+
+/// Written next to the history once the legacy home-directory log has been moved
+const MIGRATION_MARKER: &str = ".migrated-from-home";
+
+/// Location of the history file, in order of precedence:
+/// 1. `$EMORU_HISTORY`
+/// 2. `history_file` from the config
+/// 3. `<shared_history_dir>/<hostname>.jsonl`, when a shared directory is configured
+/// 4. `$XDG_STATE_HOME/emoru/history.jsonl` (`~/.local/state/emoru/history.jsonl`)
+///
+/// Unless overridden by the environment, a legacy `~/emoru_strokes.jsonl` is
+/// moved there the first time the path is resolved.
+pub fn get_log_path() -> Option<PathBuf> {
+    static LOG_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+    LOG_PATH.get_or_init(resolve_log_path).clone()
+}
+
+fn resolve_log_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(HISTORY_ENV).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+
+    let config = crate::config::Config::load();
+    let path = config
+        .history_file
+        .or_else(|| Some(config.shared_history_dir?.join(format!("{}.jsonl", host_name()))))
+        .or_else(|| {
+            let state_dir = dirs::state_dir().or_else(dirs::data_local_dir)?;
+            Some(state_dir.join("emoru").join("history.jsonl"))
+        })?;
+
+    if let Err(e) = migrate_legacy_log(&path) {
+        eprintln!("emoru: could not move history to {}: {}", path.display(), e);
+    }
+    Some(path)
+}
+
+/// History files written by other machines into the shared history
+/// directory. They are read along with this machine's file but never written.
+pub fn get_peer_logs() -> Vec<PathBuf> {
+    if std::env::var_os(HISTORY_ENV).is_some_and(|p| !p.is_empty()) {
+        return Vec::new();
+    }
+    let config = crate::config::Config::load();
+    let (None, Some(dir)) = (config.history_file, config.shared_history_dir) else {
+        return Vec::new();
+    };
+    let own = dir.join(format!("{}.jsonl", host_name()));
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut peers: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl") && *path != own)
+        .collect();
+    peers.sort();
+    peers
+}
+
+/// This machine's name, used for its file in a shared history directory.
+/// Without one, a random ID kept in the state directory stands in, so
+/// machines never share a file.
+fn host_name() -> String {
+    let valid = |name: String| Some(name.trim().to_string()).filter(|n| !n.is_empty() && !n.contains(['/', '\\']));
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .and_then(valid)
+        .or_else(|| std::env::var("HOSTNAME").ok().and_then(valid))
+        .or_else(|| std::env::var("COMPUTERNAME").ok().and_then(valid))
+        .or_else(|| {
+            let out = Command::new("hostname").output().ok().filter(|out| out.status.success())?;
+            String::from_utf8(out.stdout).ok().and_then(valid)
+        })
+        .or_else(machine_id)
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// A random ID for this machine, created once in the state directory
+fn machine_id() -> Option<String> {
+    let dir = dirs::state_dir().or_else(dirs::data_local_dir)?.join("emoru");
+    let path = dir.join("machine-id");
+    if let Some(id) = fs::read_to_string(&path).ok().map(|id| id.trim().to_string()).filter(|id| !id.is_empty()) {
+        return Some(id);
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_nanos());
+    hasher.write_u32(std::process::id());
+    let id = format!("host-{:016x}", hasher.finish());
+    create_private_dir(&dir).ok()?;
+    fs::write(&path, format!("{}\n", id)).ok()?;
+    Some(id)
+}
+
+/// Move `~/emoru_strokes.jsonl` to `path`, once. If both exist, the legacy
+/// events are put first so the log stays in time order.
+fn migrate_legacy_log(path: &Path) -> io::Result<()> {
+    let Some(dir) = path.parent() else {
+        return Ok(());
+    };
+    let marker = dir.join(MIGRATION_MARKER);
+    if marker.exists() {
+        return Ok(());
+    }
+    let Some(legacy) = dirs::home_dir().map(|h| h.join("emoru_strokes.jsonl")) else {
+        return Ok(());
+    };
+    if !legacy.exists() || legacy == path {
+        return Ok(());
+    }
+
+    create_private_dir(dir)?;
+    let _lock = lock_history(path)?;
+    let mut content = fs::read_to_string(&legacy)?;
+    match fs::read_to_string(path) {
+        Ok(current) => content.push_str(&current),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    replace_file(path, &content)?;
+    fs::write(&marker, format!("{}\n", legacy.display()))?;
+    fs::remove_file(&legacy)
+}
+
+/// Create a directory accessible by the owner only, and any missing parents
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    if dir.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+pub fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The picker's view of the history: records events subject to the log level
+/// and answers ranking queries. Without a store, nothing is recorded or recalled.
+pub struct History {
+    level: LogLevel,
+    store: Option<Box<dyn HistoryStore>>,
+    ranker: Box<dyn Ranker>,
+    /// The application the picker was opened from, whose selections are
+    /// blended into the ranking with `app_weight`
+    app: Option<String>,
+    app_weight: f64,
+}
+
+impl History {
+    pub fn new(
+        level: LogLevel,
+        store: Option<Box<dyn HistoryStore>>,
+        ranker: Box<dyn Ranker>,
+        app: Option<String>,
+        app_weight: f64,
+    ) -> Self {
+        Self { level, store, ranker, app, app_weight }
+    }
+
+    pub fn query_counts(&self) -> Vec<QueryCodeCount> {
+        self.store.as_ref().and_then(|store| store.query_counts().ok()).unwrap_or_default()
+    }
+
+    /// The emoji picked moments ago, if any, with those most often picked
+    /// together with it. Selections dated ahead of the clock (from a machine
+    /// whose clock runs fast) are ignored until their time comes.
+    pub fn recent_related(&self, limit: usize) -> Option<(String, Vec<(String, u32)>)> {
+        let store = self.store.as_ref()?;
+        let now = current_timestamp();
+        let recent = store.latest_selection(now).ok()??;
+        if now - recent.ts > CO_SELECTION_WINDOW_SECS {
+            return None;
+        }
+        let around = store.selections_around(&recent.code, CO_SELECTION_WINDOW_SECS).ok()?;
+        let related = CoOccurrence::build(&around).related(&recent.code, limit);
+        Some((recent.code, related))
+    }
+
+    pub fn frecency(&self, query: &str) -> HashMap<String, f64> {
+        let Some(store) = &self.store else {
+            return HashMap::new();
+        };
+        let now = current_timestamp();
+        let ranker = self.ranker.as_ref();
+        let global = store.frecency(ranker, query, None, now).unwrap_or_default();
+        match &self.app {
+            Some(app) if self.app_weight > 0.0 => {
+                let in_app = store.frecency(ranker, query, Some(app), now).unwrap_or_default();
+                blend_frecency(&global, &in_app, self.app_weight)
+            }
+            _ => global,
+        }
+    }
+
+    pub fn record(&self, event: &LogEvent) {
+        let allowed = match event {
+            LogEvent::Keystroke { .. } => self.level == LogLevel::Full,
+            LogEvent::Select { .. } => self.level != LogLevel::Off,
+            LogEvent::Aggregate { .. } => false,
+        };
+        if let (true, Some(store)) = (allowed, &self.store) {
+            let _ = store.append(event);
+        }
+    }
+}
+
+/// Append one event to a JSONL history
+pub fn append_event(path: &Path, event: &LogEvent) -> io::Result<()> {
+    let Some(line) = event.to_line() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    let _lock = lock_history(path)?;
+    open_private(OpenOptions::new().create(true).append(true), path)?.write_all(line.as_bytes())
+}
+
+/// Take the exclusive history lock, held until the returned file is dropped.
+/// Every write happens under it, and the history is opened only after it is
+/// taken, so appends from another picker are never lost to a concurrent rewrite.
+fn lock_history(path: &Path) -> io::Result<fs::File> {
+    let lock = open_private(
+        OpenOptions::new().create(true).write(true).truncate(false),
+        &path.with_extension("jsonl.lock"),
+    )?;
+    lock.lock()?;
+    Ok(lock)
+}
+
+/// Open a history file, creating it readable by the owner only
+pub fn open_private(options: &mut OpenOptions, path: &Path) -> io::Result<fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// A recorded emoji selection with query context
+#[derive(Clone)]
+pub struct Selection {
+    pub code: String,
+    pub query: String,
+    pub ts: u64,
+    pub app: Option<String>,
+    /// Frecency weight as of `ts`: 1.0 for a single selection, more for a compacted aggregate
+    pub weight: f64,
+    /// Number of selections this stands for
+    pub count: u32,
+    /// Whether this stands for several older selections folded by compaction
+    pub compacted: bool,
+}
+
+impl Selection {
+    /// The selection an event records, if it is one
+    pub fn from_event(event: LogEvent) -> Option<Self> {
+        match event {
+            LogEvent::Select { ts, code, query, app } => Some(Selection {
+                code,
+                query: query.to_lowercase(),
+                ts,
+                app,
+                weight: 1.0,
+                count: 1,
+                compacted: false,
+            }),
+            LogEvent::Aggregate { ts, code, query, app, weight, count } => Some(Selection {
+                code,
+                query: query.to_lowercase(),
+                ts,
+                app,
+                weight,
+                count,
+                compacted: true,
+            }),
+            LogEvent::Keystroke { .. } => None,
+        }
+    }
+}
+
+/// How many times an emoji was picked with a query, for learning aliases
+pub struct QueryCodeCount {
+    pub query: String,
+    pub code: String,
+    pub count: u32,
+}
+
+/// Selections per (query, emoji) pair, leaving out empty queries
+pub fn query_counts(selections: &[Selection]) -> Vec<QueryCodeCount> {
+    let mut counts: BTreeMap<(&str, &str), u32> = BTreeMap::new();
+    for sel in selections.iter().filter(|s| !s.query.is_empty()) {
+        *counts.entry((&sel.query, &sel.code)).or_insert(0) += sel.count;
+    }
+    counts
+        .into_iter()
+        .map(|((query, code), count)| QueryCodeCount { query: query.to_string(), code: code.to_string(), count })
+        .collect()
+}
+
+/// Read every event from a JSONL history, returning the lines that could not
+/// be read. A missing file is an empty history.
+pub fn read_events(path: &Path, mut visit: impl FnMut(LogEvent)) -> io::Result<Skipped> {
+    let mut skipped = Skipped::default();
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(skipped),
+        Err(e) => return Err(e),
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match LogEvent::from_line(&line) {
+            Ok(event) => visit(event),
+            Err(reason) => skipped.add(reason),
+        }
+    }
+
+    Ok(skipped)
+}
+
+/// Load all selections from a JSONL history, with the lines that could not be read
+pub fn read_selections(path: &Path) -> io::Result<(Vec<Selection>, Skipped)> {
+    let mut selections = Vec::new();
+    let skipped = read_events(path, |event| selections.extend(Selection::from_event(event)))?;
+    Ok((selections, skipped))
+}
+
+/// Which events `clear_history` removes. With no filters, everything is removed.
+pub struct ClearFilter {
+    /// Only events before this timestamp
+    pub before: Option<u64>,
+    /// Only selections of this emoji code (keystrokes are kept)
+    pub code: Option<String>,
+}
+
+impl ClearFilter {
+    fn matches(&self, event: &LogEvent) -> bool {
+        if self.before.is_some_and(|before| event.ts() >= before) {
+            return false;
+        }
+        match (&self.code, event) {
+            (None, _) => true,
+            (Some(wanted), LogEvent::Select { code, .. } | LogEvent::Aggregate { code, .. }) => code == wanted,
+            (Some(_), LogEvent::Keystroke { .. }) => false,
+        }
+    }
+}
+
+/// Remove matching events from a JSONL history, returning how many were removed.
+/// Lines that cannot be parsed are kept untouched.
+pub fn clear_history(path: &Path, filter: &ClearFilter) -> io::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let _lock = lock_history(path)?;
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut kept = String::with_capacity(content.len());
+    let mut removed = 0;
+    for line in content.lines() {
+        match LogEvent::from_line(line) {
+            Ok(event) if filter.matches(&event) => removed += 1,
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+    }
+
+    replace_file(path, &kept)?;
+    Ok(removed)
+}
+
+/// The incoming events that are not already in `existing`. Identical events
+/// (same time, type, emoji or key, query and app) are matched one to one, so
+/// importing the same file twice adds nothing while repeated keys typed in
+/// the same second are kept.
+pub fn new_events(existing: &[LogEvent], incoming: Vec<LogEvent>) -> Vec<LogEvent> {
+    let mut present: HashMap<_, usize> = HashMap::new();
+    for event in existing {
+        *present.entry(event.dedup_key()).or_insert(0) += 1;
+    }
+
+    incoming
+        .into_iter()
+        .filter(|event| match present.get_mut(&event.dedup_key()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Merge events into a JSONL history, keeping it in time order and skipping
+/// events it already has. Returns the number of events added.
+pub fn merge_events(path: &Path, incoming: Vec<LogEvent>) -> io::Result<usize> {
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    let _lock = lock_history(path)?;
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+
+    // Unparseable lines stay after the event they followed
+    let mut lines: Vec<(u64, String)> = Vec::new();
+    let mut existing = Vec::new();
+    let mut last_ts = 0;
+    for line in content.lines() {
+        if let Ok(event) = LogEvent::from_line(line) {
+            last_ts = event.ts();
+            existing.push(event);
+        }
+        lines.push((last_ts, format!("{}\n", line)));
+    }
+
+    let added = new_events(&existing, incoming);
+    if added.is_empty() {
+        return Ok(0);
+    }
+    lines.extend(added.iter().filter_map(|event| Some((event.ts(), event.to_line()?))));
+    lines.sort_by_key(|(ts, _)| *ts);
+
+    replace_file(path, &lines.into_iter().map(|(_, line)| line).collect::<String>())?;
+    Ok(added.len())
+}
+
+/// Write to a temporary file and rename, so a crash never leaves a truncated history
+fn replace_file(path: &Path, content: &str) -> io::Result<()> {
+    let tmp_path = path.with_extension("jsonl.tmp");
+    let mut tmp = open_private(OpenOptions::new().create(true).write(true).truncate(true), &tmp_path)?;
+    tmp.write_all(content.as_bytes())?;
+    tmp.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Raw events this much older than the retention window trigger automatic compaction
+pub const COMPACTION_SLACK_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Default)]
+pub struct CompactStats {
+    pub folded_selections: usize,
+    pub dropped_keystrokes: usize,
+    pub aggregates: usize,
+}
+
+/// Selections are folded into one aggregate per day (UTC)
+pub const COMPACTION_DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Fold selections older than the retention window into per-(emoji, query,
+/// app, day) aggregates and drop old keystrokes, in a JSONL history. The
+/// previous file is kept alongside with a `.jsonl.bak` extension.
+/// Unparseable lines are kept untouched.
+pub fn compact_history(path: &Path, retention_secs: u64, now: u64) -> io::Result<CompactStats> {
+    let mut stats = CompactStats::default();
+    if !path.exists() {
+        return Ok(stats);
+    }
+    let _lock = lock_history(path)?;
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(stats),
+        Err(e) => return Err(e),
+    };
+
+    let cutoff = now.saturating_sub(retention_secs);
+
+    // (ts, weight, count) of each folded selection or earlier aggregate, by group
+    type Group = (String, String, Option<String>, u64);
+    let mut groups: BTreeMap<Group, Vec<(u64, f64, u32)>> = BTreeMap::new();
+    let mut kept = Vec::new();
+    let mut previous_aggregates = 0;
+
+    for line in content.lines() {
+        match LogEvent::from_line(line) {
+            Ok(LogEvent::Keystroke { ts, .. }) if ts < cutoff => stats.dropped_keystrokes += 1,
+            Ok(LogEvent::Select { ts, code, query, app }) if ts < cutoff => {
+                let day = ts / COMPACTION_DAY_SECS;
+                groups.entry((code, query.to_lowercase(), app, day)).or_default().push((ts, 1.0, 1));
+                stats.folded_selections += 1;
+            }
+            Ok(LogEvent::Aggregate { ts, code, query, app, weight, count }) => {
+                let day = ts / COMPACTION_DAY_SECS;
+                groups.entry((code, query.to_lowercase(), app, day)).or_default().push((ts, weight, count));
+                previous_aggregates += 1;
+            }
+            _ => kept.push(line),
+        }
+    }
+
+    stats.aggregates = groups.len();
+    if stats.folded_selections == 0 && stats.dropped_keystrokes == 0 && previous_aggregates == groups.len() {
+        return Ok(stats);
+    }
+
+    let mut aggregates: Vec<LogEvent> = groups
+        .into_iter()
+        .map(|((code, query, app, _), folded)| {
+            let last = folded.iter().map(|(ts, _, _)| *ts).max().unwrap_or(0);
+            let weight = folded
+                .iter()
+                .map(|(ts, weight, _)| weight * 0.5_f64.powf((last - ts) as f64 / FRECENCY_HALF_LIFE_SECS))
+                .sum();
+            let count = folded.iter().map(|(_, _, count)| count).sum();
+            LogEvent::Aggregate { ts: last, code, query, app, weight, count }
+        })
+        .collect();
+    aggregates.sort_by_key(LogEvent::ts);
+
+    let mut output = String::with_capacity(content.len());
+    for line in aggregates.iter().filter_map(LogEvent::to_line) {
+        output.push_str(&line);
+    }
+    for line in kept {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    let mut backup = open_private(
+        OpenOptions::new().create(true).write(true).truncate(true),
+        &path.with_extension("jsonl.bak"),
+    )?;
+    backup.write_all(content.as_bytes())?;
+    replace_file(path, &output)?;
+    Ok(stats)
+}
+
+/// Whether a JSONL history has raw events well past the retention window.
+/// Only reads up to the first raw event, since the log is in time order.
+pub fn needs_compaction(path: &Path, retention_secs: u64, now: u64) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let threshold = now.saturating_sub(retention_secs + COMPACTION_SLACK_SECS);
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| LogEvent::from_line(&line).ok())
+        .find(|event| !matches!(event, LogEvent::Aggregate { .. }))
+        .is_some_and(|event| event.ts() < threshold)
+}
+
+/// Parse a `YYYY-MM-DD` date as a Unix timestamp at midnight UTC
+pub fn parse_date(date: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid date '{}', expected YYYY-MM-DD", date);
+    let parts: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let month: i64 = month.parse().map_err(|_| invalid())?;
+    let day: i64 = day.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return Err(invalid());
+    }
+
+    Ok((days_from_civil(year, month, day) * 86_400) as u64)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Civil date (year, month, day) for a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a Unix timestamp as a `YYYY-MM-DD` date (UTC)
+pub fn format_date(ts: u64) -> String {
+    let (year, month, day) = civil_from_days((ts / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::ranker;
+
+    pub const DAY: u64 = 24 * 60 * 60;
+    const HOUR: u64 = 60 * 60;
+
+    /// A history path in a fresh temporary directory
+    pub fn temp_history(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("emoru-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("history.jsonl")
+    }
+
+    fn select(ts: u64, code: &str, query: &str) -> LogEvent {
+        LogEvent::Select { ts, code: code.to_string(), query: query.to_string(), app: None }
+    }
+
+    /// A midday time, so no bucket edge falls between selections made the same morning
+    pub const NOW: u64 = 1_700_000_000 / DAY * DAY + 12 * HOUR;
+
+    /// Selections and keystrokes on several mornings over the months before `NOW`
+    pub fn sample_events() -> Vec<LogEvent> {
+        let mut events = Vec::new();
+        for days_ago in [200, 95, 60, 40, 31, 20, 10, 3, 1] {
+            for hour in [1, 4, 9] {
+                let ts = NOW - days_ago * DAY - 12 * HOUR + hour * HOUR;
+                events.push(LogEvent::Keystroke { ts, key: "s".to_string() });
+                events.push(select(ts, "1F600", "smile"));
+                events.push(select(ts + 60, "1F60A", "sm"));
+                events.push(select(ts + 120, "2764", "heart"));
+            }
+        }
+        events
+    }
+
+    /// Check that every ranker scores compacted selections as it did the raw ones
+    pub fn assert_same_scores(before: &[Selection], after: &[Selection], now: u64) {
+        assert!(after.len() < before.len());
+        for spec in ["frecency", "frecency:30", "similarity", "buckets", "count", "recency"] {
+            let ranker = ranker::parse(spec).unwrap();
+            // Another half-life is off by at most a day of decay
+            let tolerance = if spec == "frecency:30" { 0.03 } else { 1e-9 };
+            for query in ["", "s", "smile", "heart"] {
+                let expected = ranker.scores(before, query, None, now);
+                let actual = ranker.scores(after, query, None, now);
+                assert_eq!(expected.len(), actual.len(), "{} {:?}", spec, query);
+                for (code, score) in expected {
+                    let off = (actual[&code] - score).abs();
+                    assert!(off <= score * tolerance, "{} {:?} {}: {} vs {}", spec, query, code, score, actual[&code]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compaction_keeps_scores() {
+        let path = temp_history("compaction");
+        merge_events(&path, sample_events()).unwrap();
+
+        // Compacting again later folds more days into the existing aggregates
+        for (retention_days, now) in [(30, NOW), (7, NOW + 5 * DAY)] {
+            let (before, _) = read_selections(&path).unwrap();
+            let stats = compact_history(&path, retention_days * DAY, now).unwrap();
+            assert!(stats.folded_selections > 0);
+            let (after, _) = read_selections(&path).unwrap();
+            assert_same_scores(&before, &after, now);
+        }
+
+        let cutoff = NOW + 5 * DAY - 7 * DAY;
+        let mut old_keystrokes = 0;
+        read_events(&path, |event| {
+            if matches!(event, LogEvent::Keystroke { ts, .. } if ts < cutoff) {
+                old_keystrokes += 1;
+            }
+        })
+        .unwrap();
+        assert_eq!(old_keystrokes, 0);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn formats_and_parses_dates() {
+        assert_eq!(parse_date("2024-02-29"), Ok(1_709_164_800));
+        assert_eq!(format_date(1_709_164_800 + 3600), "2024-02-29");
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("24-1").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::locale::{emoji_to_code, fold, load_locale, normalize_code, BASE_LOCALE};
+
+/// An emoji from the index with its display text and search keywords
+#[derive(Clone)]
+pub struct EmojiRecord {
+    pub emoji: String,
+    pub description: String,
+    pub code: String,
+    /// Folded keywords from every active locale
+    pub words: Vec<String>,
+    /// The same keywords as written, for display
+    pub keywords: Vec<String>,
+    /// Aliases that find this emoji, added by hand or learned
+    pub aliases: Vec<String>,
+    /// Unicode metadata (group, version, skin tone), when the emoji is known
+    pub meta: Option<&'static emojis::Emoji>,
+}
+
+impl EmojiRecord {
+    /// Parse an `emoji| description | CODE` index line
+    pub fn parse(line: &str) -> Option<Self> {
+        let parts: Vec<&str> = line.split("| ").collect();
+        if parts.len() < 3 {
+            return None;
+        }
+        let emoji = parts[0].to_string();
+        let description = parts[1].trim().to_string();
+        Some(Self {
+            meta: emojis::get(&emoji),
+            emoji,
+            words: description.split_whitespace().map(fold).collect(),
+            keywords: description.split_whitespace().map(String::from).collect(),
+            aliases: Vec::new(),
+            description,
+            code: parts[2].trim().to_string(),
+        })
+    }
+}
+
+/// Find the index entry for an emoji, ignoring variation selectors
+pub fn find_emoji<'a>(emojis: &'a [EmojiRecord], emoji: &str) -> Option<&'a EmojiRecord> {
+    let wanted = normalize_code(&emoji_to_code(emoji));
+    emojis.iter().find(|record| normalize_code(&record.code) == wanted)
+}
+
+/// Find the index entry for a code (`1F389`), ignoring variation selectors
+pub fn find_code<'a>(emojis: &'a [EmojiRecord], code: &str) -> Option<&'a EmojiRecord> {
+    let wanted = normalize_code(code);
+    emojis.iter().find(|record| normalize_code(&record.code) == wanted)
+}
+
+/// Find an index entry given either the emoji itself or its code (`1F389`)
+pub fn lookup<'a>(emojis: &'a [EmojiRecord], text: &str) -> Option<&'a EmojiRecord> {
+    find_emoji(emojis, text).or_else(|| emojis.iter().find(|r| r.code.eq_ignore_ascii_case(text)))
+}
+
+/// Human-readable name of a Unicode emoji group, as used by `group:` filters
+pub fn group_name(group: emojis::Group) -> &'static str {
+    match group {
+        emojis::Group::SmileysAndEmotion => "smileys & emotion",
+        emojis::Group::PeopleAndBody => "people & body",
+        emojis::Group::AnimalsAndNature => "animals & nature",
+        emojis::Group::FoodAndDrink => "food & drink",
+        emojis::Group::TravelAndPlaces => "travel & places",
+        emojis::Group::Activities => "activities",
+        emojis::Group::Objects => "objects",
+        emojis::Group::Symbols => "symbols",
+        emojis::Group::Flags => "flags",
+    }
+}
+
+/// Find the data directory containing emoji files.
+/// Searches in order:
+/// 1. ./data (next to executable)
+/// 2. ../data (for development)
+/// 3. ~/.emoru/data
+/// 4. ~/emoji_picker_images (legacy)
+pub fn find_data_dir() -> Option<PathBuf> {
+    // Next to executable
+    if let Ok(exe) = std::env::current_exe() {
+        let exe_dir = exe.parent().unwrap_or(&exe);
+        let data_dir = exe_dir.join("data");
+        if data_dir.exists() {
+            return Some(data_dir);
+        }
+    }
+
+    // Development: ./data
+    let local_data = PathBuf::from("data");
+    if local_data.exists() {
+        return Some(local_data);
+    }
+
+    // User config: ~/.emoru/data
+    if let Some(home) = dirs::home_dir() {
+        let user_data = home.join(".emoru").join("data");
+        if user_data.exists() {
+            return Some(user_data);
+        }
+
+        // Legacy location
+        let legacy = home.join("emoji_picker_images");
+        if legacy.exists() {
+            // For legacy, return parent since we expect data/emoji_picker_images structure
+            return Some(home.clone());
+        }
+    }
+
+    None
+}
+
+/// Load the emoji index from the data directory (or the legacy home
+/// location) and merge in keywords from the given locales
+pub fn load_index(data_dir: Option<&Path>, locales: &[String]) -> Vec<EmojiRecord> {
+    let mut content = None;
+
+    // Try bundled data first
+    if let Some(data_dir) = data_dir {
+        content = fs::read_to_string(data_dir.join("emojis9.txt")).ok();
+    }
+
+    // Fallback to home directory (legacy)
+    if content.is_none() {
+        if let Some(home) = dirs::home_dir() {
+            content = fs::read_to_string(home.join("emojis9.txt")).ok();
+        }
+    }
+
+    let mut emojis: Vec<EmojiRecord> = content
+        .map(|c| c.lines().filter_map(EmojiRecord::parse).collect())
+        .unwrap_or_default();
+
+    if let Some(data_dir) = data_dir {
+        apply_locales(&mut emojis, data_dir, locales);
+    }
+    emojis
+}
+
+/// Merge keywords from the configured locales into the index.
+/// The first locale that describes an emoji provides its display text;
+/// English keywords are only searchable when English is a selected locale,
+/// or when no selected locale describes the emoji.
+fn apply_locales(emojis: &mut [EmojiRecord], data_dir: &Path, locales: &[String]) {
+    if locales.iter().all(|l| l == BASE_LOCALE) {
+        return;
+    }
+
+    let tables: Vec<Option<HashMap<String, String>>> = locales
+        .iter()
+        .map(|lang| (lang != BASE_LOCALE).then(|| load_locale(data_dir, lang)))
+        .collect();
+
+    for record in emojis {
+        let key = normalize_code(&record.code);
+        let mut display = None;
+        let mut words = Vec::new();
+        let mut keywords = Vec::new();
+
+        for table in &tables {
+            let description = match table {
+                None => Some(&record.description),
+                Some(table) => table.get(&key),
+            };
+            if let Some(description) = description {
+                display.get_or_insert_with(|| description.clone());
+                words.extend(description.split_whitespace().map(fold));
+                keywords.extend(description.split_whitespace().map(String::from));
+            }
+        }
+
+        // Keep the English keywords rather than making the emoji unfindable
+        if let Some(display) = display {
+            record.description = display;
+            record.words = words;
+            record.keywords = keywords;
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+#[cfg(unix)]
+use std::fs::{self, File, OpenOptions};
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+#[cfg(unix)]
+use crate::config::get_config_dir;
+
+/// What launching the picker does while another picker is open
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SingleInstance {
+    /// Close the open picker, so the same hotkey opens and closes it
+    #[default]
+    Toggle,
+    /// Bring the open picker to the front
+    Focus,
+    /// Open another picker
+    Off,
+}
+
+impl SingleInstance {
+    #[cfg(unix)]
+    fn message(self) -> &'static str {
+        match self {
+            SingleInstance::Toggle => "toggle",
+            SingleInstance::Focus => "focus",
+            SingleInstance::Off => "",
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_message(message: &str) -> Option<Self> {
+        match message {
+            "toggle" => Some(SingleInstance::Toggle),
+            "focus" => Some(SingleInstance::Focus),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of starting the picker while another one may be open
+pub enum Launch {
+    /// Open a picker, holding the lock when single-instance mode is on
+    Open(Option<Lock>),
+    /// An open picker was told to toggle or focus instead
+    HandedOver,
+}
+
+/// The socket the open picker listens on, in `$XDG_RUNTIME_DIR` when there is one
+#[cfg(unix)]
+fn socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir().or_else(get_config_dir).map(|dir| dir.join("emoru.sock"))
+}
+
+/// Lock a file next to the socket until the returned file is dropped, so
+/// only one launch at a time checks for an open picker and takes the socket
+#[cfg(unix)]
+fn lock_launches(socket: &Path) -> io::Result<File> {
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(socket.with_extension("lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Held by the open picker; removes its socket when dropped
+#[cfg(unix)]
+pub struct Lock {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Take the single-instance lock, or pass the launch on to the open picker
+#[cfg(unix)]
+pub fn acquire(mode: SingleInstance) -> io::Result<Launch> {
+    match socket_path().filter(|_| mode != SingleInstance::Off) {
+        Some(path) => acquire_at(path, mode),
+        None => Ok(Launch::Open(None)),
+    }
+}
+
+#[cfg(unix)]
+fn acquire_at(path: PathBuf, mode: SingleInstance) -> io::Result<Launch> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Otherwise two launches at once could both find no picker, and the
+    // second would remove the first one's socket
+    let _launching = lock_launches(&path)?;
+    match UnixStream::connect(&path) {
+        Ok(mut stream) => {
+            writeln!(stream, "{}", mode.message())?;
+            Ok(Launch::HandedOver)
+        }
+        Err(_) => {
+            // Left behind by a picker that crashed
+            let _ = fs::remove_file(&path);
+            let listener = UnixListener::bind(&path)?;
+            Ok(Launch::Open(Some(Lock { listener, path })))
+        }
+    }
+}
+
+/// Single-instance mode needs Unix sockets; elsewhere every launch opens a picker
+#[cfg(not(unix))]
+pub struct Lock;
+
+#[cfg(not(unix))]
+pub fn acquire(_mode: SingleInstance) -> io::Result<Launch> {
+    Ok(Launch::Open(None))
+}
+
+impl Lock {
+    /// Call `on_request` with what each later launch asks for, on a background thread
+    #[cfg(unix)]
+    pub fn listen(&self, on_request: impl Fn(SingleInstance) + Send + 'static) -> io::Result<()> {
+        let listener = self.listener.try_clone()?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut line = String::new();
+                if BufReader::new(stream).read_line(&mut line).is_ok() {
+                    if let Some(request) = SingleInstance::from_message(line.trim()) {
+                        on_request(request);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(&self, _on_request: impl Fn(SingleInstance) + Send + 'static) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::*;
+
+    fn temp_socket(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("emoru-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("emoru.sock")
+    }
+
+    #[test]
+    fn one_of_simultaneous_launches_opens() {
+        let path = temp_socket("instance-race");
+        for _ in 0..20 {
+            let barrier = Arc::new(Barrier::new(16));
+            let launches: Vec<_> = (0..16)
+                .map(|_| {
+                    let (path, barrier) = (path.clone(), barrier.clone());
+                    thread::spawn(move || {
+                        barrier.wait();
+                        let launch = acquire_at(path, SingleInstance::Focus);
+                        // Keep the lock until every launch has decided
+                        barrier.wait();
+                        matches!(launch, Ok(Launch::Open(Some(_))))
+                    })
+                })
+                .collect();
+            let opened = launches.into_iter().filter_map(|launch| launch.join().unwrap().then_some(())).count();
+            assert_eq!(opened, 1);
+        }
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn takes_over_a_stale_socket() {
+        let path = temp_socket("instance-stale");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // A socket nobody listens on, as a crashed picker leaves behind
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(matches!(acquire_at(path.clone(), SingleInstance::Toggle).unwrap(), Launch::Open(Some(_))));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Something a key can do in the picker
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// Paste the highlighted emoji
+    Select,
+    Close,
+    Next,
+    Prev,
+    PageDown,
+    PageUp,
+    /// Move the cursor, or choose a related emoji while the search is empty
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    DeleteWordForward,
+    KillToStart,
+    KillToEnd,
+    /// Empty the search
+    Clear,
+    /// Paste the clipboard into the search
+    Paste,
+    Pin,
+    Hide,
+    /// Show or hide the preview panel
+    TogglePreview,
+    /// Paste the result in this visible row, from 0
+    SelectRow(usize),
+}
+
+const ACTIONS: [(&str, Action); 32] = [
+    ("select", Action::Select),
+    ("close", Action::Close),
+    ("next", Action::Next),
+    ("prev", Action::Prev),
+    ("page-down", Action::PageDown),
+    ("page-up", Action::PageUp),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("word-left", Action::WordLeft),
+    ("word-right", Action::WordRight),
+    ("line-start", Action::LineStart),
+    ("line-end", Action::LineEnd),
+    ("delete-back", Action::DeleteBack),
+    ("delete-forward", Action::DeleteForward),
+    ("delete-word-back", Action::DeleteWordBack),
+    ("delete-word-forward", Action::DeleteWordForward),
+    ("kill-to-start", Action::KillToStart),
+    ("kill-to-end", Action::KillToEnd),
+    ("clear", Action::Clear),
+    ("paste", Action::Paste),
+    ("pin", Action::Pin),
+    ("hide", Action::Hide),
+    ("toggle-preview", Action::TogglePreview),
+    ("select-1", Action::SelectRow(0)),
+    ("select-2", Action::SelectRow(1)),
+    ("select-3", Action::SelectRow(2)),
+    ("select-4", Action::SelectRow(3)),
+    ("select-5", Action::SelectRow(4)),
+    ("select-6", Action::SelectRow(5)),
+    ("select-7", Action::SelectRow(6)),
+    ("select-8", Action::SelectRow(7)),
+    ("select-9", Action::SelectRow(8)),
+];
+
+/// Keys that are not typed text, as sent by the picker
+const NAMED_KEYS: [&str; 13] = [
+    "enter", "tab", "escape", "up", "down", "left", "right", "home", "end", "pageup", "pagedown", "backspace",
+    "delete",
+];
+
+const DEFAULT: &[(&str, Action)] = &[
+    ("enter", Action::Select),
+    ("tab", Action::Select),
+    ("escape", Action::Close),
+    ("down", Action::Next),
+    ("up", Action::Prev),
+    ("pagedown", Action::PageDown),
+    ("pageup", Action::PageUp),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("ctrl-left", Action::WordLeft),
+    ("alt-b", Action::WordLeft),
+    ("ctrl-right", Action::WordRight),
+    ("alt-f", Action::WordRight),
+    ("home", Action::LineStart),
+    ("ctrl-a", Action::LineStart),
+    ("end", Action::LineEnd),
+    ("ctrl-e", Action::LineEnd),
+    ("backspace", Action::DeleteBack),
+    ("delete", Action::DeleteForward),
+    ("ctrl-w", Action::DeleteWordBack),
+    ("alt-backspace", Action::DeleteWordBack),
+    ("alt-d", Action::DeleteWordForward),
+    ("ctrl-u", Action::KillToStart),
+    ("ctrl-k", Action::KillToEnd),
+    ("ctrl-backspace", Action::Clear),
+    ("ctrl-v", Action::Paste),
+    ("alt-p", Action::Pin),
+    ("alt-h", Action::Hide),
+    ("alt-i", Action::TogglePreview),
+];
+
+/// Vim-style navigation on top of the default keys
+const VIM: &[(&str, Action)] = &[
+    ("ctrl-j", Action::Next),
+    ("ctrl-k", Action::Prev),
+    ("ctrl-f", Action::PageDown),
+    ("ctrl-b", Action::PageUp),
+    ("ctrl-h", Action::DeleteBack),
+];
+
+/// Emacs-style navigation on top of the default keys
+const EMACS: &[(&str, Action)] = &[
+    ("ctrl-n", Action::Next),
+    ("ctrl-p", Action::Prev),
+    ("ctrl-v", Action::PageDown),
+    ("alt-v", Action::PageUp),
+    ("ctrl-f", Action::Right),
+    ("ctrl-b", Action::Left),
+    ("ctrl-d", Action::DeleteForward),
+    ("ctrl-h", Action::DeleteBack),
+    ("ctrl-g", Action::Close),
+    ("ctrl-y", Action::Paste),
+];
+
+fn action_name(action: Action) -> &'static str {
+    ACTIONS.iter().find(|(_, a)| *a == action).map_or("", |(name, _)| name)
+}
+
+fn parse_action(name: &str) -> Option<Action> {
+    ACTIONS.iter().find(|(n, _)| *n == name).map(|(_, action)| *action)
+}
+
+/// A key name as the picker sends it: `ctrl-` and `alt-` (in that order),
+/// then a named key or a single character, as in `ctrl-j` or `alt-backspace`.
+/// Accepts `+` between parts, `control`, `return` and `esc`, and any case.
+pub fn normalize_key(name: &str) -> Result<String, String> {
+    let lower = name.trim().to_lowercase();
+    let mut parts: Vec<&str> = lower.split(['-', '+']).collect();
+    // A trailing `-` or `+` is the key itself
+    if parts.len() > 1 && parts.last() == Some(&"") {
+        parts.pop();
+        *parts.last_mut().expect("parts is not empty") = if lower.ends_with('+') { "+" } else { "-" };
+    }
+    let (key, modifiers) = parts.split_last().ok_or_else(|| format!("invalid key '{}'", name))?;
+
+    let (mut ctrl, mut alt, mut shift) = (false, false, false);
+    for modifier in modifiers {
+        match *modifier {
+            "ctrl" | "control" => ctrl = true,
+            "alt" => alt = true,
+            "shift" => shift = true,
+            _ => return Err(format!("invalid key '{}': unknown modifier '{}'", name, modifier)),
+        }
+    }
+    let key = match *key {
+        "return" => "enter",
+        "esc" => "escape",
+        key => key,
+    };
+    if !NAMED_KEYS.contains(&key) && key.chars().count() != 1 {
+        return Err(format!("invalid key '{}': unknown key '{}'", name, key));
+    }
+    // Shift only reaches the picker as Shift+Tab; on characters it changes the text
+    if shift && key != "tab" {
+        return Err(format!("invalid key '{}': shift only combines with tab", name));
+    }
+
+    Ok(format!(
+        "{}{}{}{}",
+        if ctrl { "ctrl-" } else { "" },
+        if alt { "alt-" } else { "" },
+        if shift { "shift-" } else { "" },
+        key
+    ))
+}
+
+/// The text a key types, if it is not a named or modified key. Slint sends
+/// function keys as private-use characters, which type nothing.
+pub fn typed_text(key: &str) -> Option<&str> {
+    let modified = ["ctrl-", "alt-", "shift-"].iter().any(|m| key.starts_with(m) && key.len() > m.len());
+    let special = key.chars().any(|c| c.is_control() || ('\u{f700}'..='\u{f8ff}').contains(&c));
+    (!modified && !special && !NAMED_KEYS.contains(&key)).then_some(key)
+}
+
+/// Which action each key runs. Keys without a binding type their text.
+pub struct Keymap {
+    bindings: HashMap<String, Action>,
+}
+
+impl Keymap {
+    /// The default keys, plus a `default`, `vim` or `emacs` preset, plus
+    /// bindings from the config (key → action, or `none` to unbind). Invalid
+    /// and conflicting bindings are reported and skipped. Alt+1..9 pick the
+    /// visible results, as do plain digits with `digits`.
+    pub fn new(preset: &str, overrides: &BTreeMap<String, String>, digits: bool) -> Self {
+        Self::with_replaced(preset, overrides, digits).0
+    }
+
+    /// Like `new`, also returning the default keys the preset binds
+    /// differently, for `emoru keys`
+    pub fn with_replaced(preset: &str, overrides: &BTreeMap<String, String>, digits: bool) -> (Self, Vec<String>) {
+        let (keymap, problems, replaced) = Self::build(preset, overrides, digits);
+        for problem in problems {
+            eprintln!("emoru: {}", problem);
+        }
+        (keymap, replaced)
+    }
+
+    /// The keymap, its invalid and conflicting bindings (including config
+    /// bindings replacing a preset key), and the defaults the preset replaces.
+    /// A preset replacing a default is not listed once the config binds the key.
+    fn build(preset: &str, overrides: &BTreeMap<String, String>, digits: bool) -> (Self, Vec<String>, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut replaced = Vec::new();
+        let mut bindings: HashMap<String, Action> = DEFAULT.iter().map(|(key, a)| (key.to_string(), *a)).collect();
+        for row in 0..9 {
+            bindings.insert(format!("alt-{}", row + 1), Action::SelectRow(row));
+            if digits {
+                bindings.insert((row + 1).to_string(), Action::SelectRow(row));
+            }
+        }
+        let preset_bindings = match preset {
+            "default" => &[][..],
+            "vim" => VIM,
+            "emacs" => EMACS,
+            _ => {
+                problems.push(format!("unknown keymap '{}', expected default, vim or emacs", preset));
+                &[][..]
+            }
+        };
+        let user = user_bindings(overrides, &mut problems);
+
+        for (key, action) in preset_bindings {
+            let default = bindings.insert(key.to_string(), *action);
+            if let Some(default) = default.filter(|default| default != action && !user.contains_key(*key)) {
+                replaced.push(format!(
+                    "keymap '{}' binds '{}' to '{}' instead of '{}'",
+                    preset,
+                    key,
+                    action_name(*action),
+                    action_name(default)
+                ));
+            }
+        }
+
+        for (key, action) in user {
+            let from_preset = preset_bindings.iter().find(|(k, _)| *k == key).map(|(_, a)| *a);
+            if let Some(replaced) = from_preset.filter(|a| Some(*a) != action) {
+                problems.push(format!(
+                    "keybinding '{}' replaces '{}' from the {} keymap",
+                    key,
+                    action_name(replaced),
+                    preset
+                ));
+            }
+            match action {
+                Some(action) => bindings.insert(key, action),
+                None => bindings.remove(&key),
+            };
+        }
+
+        for required in [Action::Select, Action::Close] {
+            if !bindings.values().any(|a| *a == required) {
+                problems.push(format!("keybindings leave no key for '{}'", action_name(required)));
+            }
+        }
+
+        (Self { bindings }, problems, replaced)
+    }
+
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.bindings.get(key).copied()
+    }
+
+    /// Every action by name with its sorted keys, in a fixed order
+    pub fn by_action(&self) -> Vec<(&'static str, Vec<&str>)> {
+        ACTIONS
+            .iter()
+            .map(|(name, action)| {
+                let mut keys: Vec<&str> =
+                    self.bindings.iter().filter(|(_, a)| *a == action).map(|(key, _)| key.as_str()).collect();
+                keys.sort_unstable();
+                (*name, keys)
+            })
+            .collect()
+    }
+}
+
+/// Valid bindings from the config by normalized key, `None` for unbound keys.
+/// Reports unknown keys and actions, plain characters (they could no longer
+/// be typed) and keys bound twice under different spellings.
+fn user_bindings(overrides: &BTreeMap<String, String>, problems: &mut Vec<String>) -> BTreeMap<String, Option<Action>> {
+    let mut bindings = BTreeMap::new();
+    let mut spellings: BTreeMap<String, &str> = BTreeMap::new();
+
+    for (name, action_spec) in overrides {
+        let key = match normalize_key(name) {
+            Ok(key) => key,
+            Err(e) => {
+                problems.push(format!("keybinding: {}", e));
+                continue;
+            }
+        };
+        if key.chars().count() == 1 {
+            problems.push(format!("keybinding '{}': single characters are typed into the search, add ctrl- or alt-", name));
+            continue;
+        }
+        let action = match action_spec.as_str() {
+            "none" => None,
+            spec => match parse_action(spec) {
+                Some(action) => Some(action),
+                None => {
+                    problems.push(format!("keybinding '{}': unknown action '{}'", name, spec));
+                    continue;
+                }
+            },
+        };
+        if let Some(other) = spellings.get(&key) {
+            if bindings.get(&key) != Some(&action) {
+                problems.push(format!(
+                    "keybindings '{}' and '{}' are the same key with different actions, ignoring both",
+                    other, name
+                ));
+                bindings.remove(&key);
+            }
+            continue;
+        }
+        spellings.insert(key.clone(), name);
+        bindings.insert(key, action);
+    }
+
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(preset: &str, overrides: &[(&str, &str)]) -> (Keymap, Vec<String>, Vec<String>) {
+        let overrides = overrides.iter().map(|(k, a)| (k.to_string(), a.to_string())).collect();
+        Keymap::build(preset, &overrides, false)
+    }
+
+    #[test]
+    fn normalizes_keys() {
+        assert_eq!(normalize_key("Ctrl+J").unwrap(), "ctrl-j");
+        assert_eq!(normalize_key("alt-control-Return").unwrap(), "ctrl-alt-enter");
+        assert_eq!(normalize_key("shift-tab").unwrap(), "shift-tab");
+        assert_eq!(normalize_key("ctrl--").unwrap(), "ctrl--");
+        assert_eq!(normalize_key("alt++").unwrap(), "alt-+");
+        assert!(normalize_key("hyper-j").is_err());
+        assert!(normalize_key("ctrl-space bar").is_err());
+        assert!(normalize_key("shift-a").is_err());
+    }
+
+    #[test]
+    fn types_only_plain_text() {
+        assert_eq!(typed_text("a"), Some("a"));
+        assert_eq!(typed_text("-"), Some("-"));
+        assert_eq!(typed_text("ctrl-a"), None);
+        assert_eq!(typed_text("enter"), None);
+        assert_eq!(typed_text("\u{f704}"), None);
+    }
+
+    #[test]
+    fn lists_presets_replacing_defaults() {
+        let (keymap, _, replaced) = build("default", &[]);
+        assert_eq!(replaced, Vec::<String>::new());
+        assert_eq!(keymap.action("ctrl-k"), Some(Action::KillToEnd));
+
+        // The presets' own bindings are not problems
+        let (keymap, problems, replaced) = build("vim", &[]);
+        assert_eq!(keymap.action("ctrl-k"), Some(Action::Prev));
+        assert_eq!(problems, Vec::<String>::new());
+        assert_eq!(replaced, ["keymap 'vim' binds 'ctrl-k' to 'prev' instead of 'kill-to-end'"]);
+
+        let (keymap, problems, replaced) = build("emacs", &[]);
+        assert_eq!(keymap.action("ctrl-v"), Some(Action::PageDown));
+        assert_eq!(problems, Vec::<String>::new());
+        assert_eq!(replaced, ["keymap 'emacs' binds 'ctrl-v' to 'page-down' instead of 'paste'"]);
+
+        // Binding the key in the config settles it
+        let (keymap, problems, replaced) = build("vim", &[("ctrl-k", "prev"), ("alt-k", "kill-to-end")]);
+        assert_eq!(problems, Vec::<String>::new());
+        assert_eq!(replaced, Vec::<String>::new());
+        assert_eq!(keymap.action("alt-k"), Some(Action::KillToEnd));
+    }
+
+    #[test]
+    fn reports_bindings_replacing_presets() {
+        let (keymap, problems, _) = build("vim", &[("ctrl-j", "none"), ("ctrl-f", "right"), ("ctrl-k", "prev")]);
+        assert_eq!(keymap.action("ctrl-j"), None);
+        assert_eq!(keymap.action("ctrl-f"), Some(Action::Right));
+        assert_eq!(
+            problems,
+            [
+                "keybinding 'ctrl-f' replaces 'page-down' from the vim keymap",
+                "keybinding 'ctrl-j' replaces 'next' from the vim keymap",
+            ]
+        );
+        // Replacing a default is what the config is for
+        let (_, problems, _) = build("default", &[("tab", "none"), ("ctrl-k", "clear")]);
+        assert_eq!(problems, Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_invalid_and_conflicting_bindings() {
+        let (keymap, problems, _) = build(
+            "default",
+            &[("Ctrl+L", "clear"), ("ctrl-l", "pin"), ("x", "select"), ("alt-q", "quit"), ("enter", "none")],
+        );
+        assert_eq!(keymap.action("ctrl-l"), None);
+        assert_eq!(keymap.action("enter"), None);
+        assert_eq!(
+            problems,
+            [
+                "keybinding 'alt-q': unknown action 'quit'",
+                "keybindings 'Ctrl+L' and 'ctrl-l' are the same key with different actions, ignoring both",
+                "keybinding 'x': single characters are typed into the search, add ctrl- or alt-",
+            ]
+        );
+
+        let (_, problems, _) = build("nano", &[("enter", "none"), ("tab", "none")]);
+        assert_eq!(
+            problems,
+            ["unknown keymap 'nano', expected default, vim or emacs", "keybindings leave no key for 'select'"]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::Config;
+
+/// The locale of the bundled `emojis9.txt` index
+pub const BASE_LOCALE: &str = "en";
+
+/// Determine which locales to search, in display-priority order.
+/// Configured locales are used as-is; otherwise the environment locale is
+/// used with English as a fallback.
+pub fn resolve_locales(config: &Config) -> Vec<String> {
+    if !config.locales.is_empty() {
+        return config.locales.iter().map(|l| normalize_locale(l)).collect();
+    }
+
+    let mut locales = Vec::new();
+    let env_value = ["LANGUAGE", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|v| !v.is_empty());
+
+    if let Some(value) = env_value {
+        // LANGUAGE may hold a colon-separated priority list
+        for part in value.split(':') {
+            let lang = normalize_locale(part);
+            if !lang.is_empty() && lang != "c" && lang != "posix" && !locales.contains(&lang) {
+                locales.push(lang);
+            }
+        }
+    }
+
+    if !locales.iter().any(|l| l == BASE_LOCALE) {
+        locales.push(BASE_LOCALE.to_string());
+    }
+    locales
+}
+
+/// Reduce a locale like `de_DE.UTF-8@euro` to its language code (`de`)
+fn normalize_locale(locale: &str) -> String {
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Fold a string for matching: Unicode lowercase, `ß` expanded to `ss`,
+/// and diacritics removed, so "Café" and "cafe" compare equal
+pub fn fold(s: &str) -> String {
+    s.chars().flat_map(fold_char).collect()
+}
+
+/// Fold a single character; may produce zero or more characters
+pub fn fold_char(c: char) -> impl Iterator<Item = char> {
+    let lowered: String = match c {
+        'ß' | 'ẞ' => "ss".to_string(),
+        _ => c.to_lowercase().collect(),
+    };
+    lowered
+        .nfd()
+        .filter(|ch| !is_combining_mark(*ch))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Strip variation selectors so codes from different sources can be joined
+/// (CLDR omits U+FE0F where `emojis9.txt` sometimes includes it)
+pub fn normalize_code(code: &str) -> String {
+    code.split('_')
+        .filter(|part| *part != "FE0F")
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Load a locale keyword file from `<data_dir>/locales/<lang>.txt`.
+/// The file uses the same `emoji| keywords | CODE` format as `emojis9.txt`.
+/// Returns descriptions keyed by normalized code.
+pub fn load_locale(data_dir: &Path, lang: &str) -> HashMap<String, String> {
+    let mut descriptions = HashMap::new();
+    let path = data_dir.join("locales").join(format!("{}.txt", lang));
+
+    if let Ok(content) = fs::read_to_string(&path) {
+        for line in content.lines() {
+            let parts: Vec<&str> = line.split("| ").collect();
+            if parts.len() >= 3 {
+                let description = parts[1].trim().to_string();
+                let code = normalize_code(parts[2].trim());
+                descriptions.insert(code, description);
+            }
+        }
+    }
+
+    descriptions
+}
+
+#[derive(Deserialize)]
+struct CldrFile {
+    annotations: CldrAnnotations,
+}
+
+#[derive(Deserialize)]
+struct CldrAnnotations {
+    annotations: HashMap<String, CldrEntry>,
+}
+
+#[derive(Deserialize)]
+struct CldrEntry {
+    #[serde(default)]
+    default: Vec<String>,
+    #[serde(default)]
+    tts: Vec<String>,
+}
+
+/// Convert an emoji string to the code format used for file names (`1F469_200D_1F680`)
+pub fn emoji_to_code(emoji: &str) -> String {
+    emoji
+        .chars()
+        .map(|c| format!("{:04X}", c as u32))
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Convert a CLDR `annotations.json` (from the cldr-json distribution)
+/// into locale keyword file lines, sorted by code
+pub fn import_cldr(path: &Path) -> Result<Vec<String>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let file: CldrFile =
+        serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut lines: Vec<(String, String)> = file
+        .annotations
+        .annotations
+        .into_iter()
+        .filter_map(|(emoji, entry)| {
+            // The name comes first so it leads the displayed description
+            let mut words: Vec<String> = Vec::new();
+            for phrase in entry.tts.iter().chain(entry.default.iter()) {
+                for word in phrase.split_whitespace() {
+                    let word = word.replace('|', "");
+                    if !word.is_empty() && !words.iter().any(|w| fold(w) == fold(&word)) {
+                        words.push(word);
+                    }
+                }
+            }
+            if words.is_empty() {
+                return None;
+            }
+            let code = emoji_to_code(&emoji);
+            Some((code.clone(), format!("{}| {} | {}", emoji, words.join(" "), code)))
+        })
+        .collect();
+
+    lines.sort();
+    Ok(lines.into_iter().map(|(_, line)| line).collect())
+}
@@ -1,190 +1,68 @@
-use std::fs::{self, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::fs;
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::time::{SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
 
 use arboard::Clipboard;
-use serde::{Deserialize, Serialize};
+use clap::Parser;
 use slint::{Image, Rgba8Pixel, SharedPixelBuffer, ModelRc, VecModel, SharedString};
+use slint::winit_030::WinitWindowAccessor;
+
+mod active_app;
+mod alias;
+#[cfg(target_os = "linux")]
+mod caret;
+mod cli;
+mod config;
+mod convert;
+mod editor;
+mod emoticon;
+mod eval;
+mod favorites;
+mod history;
+mod index;
+mod instance;
+mod keymap;
+mod locale;
+mod placement;
+mod query;
+mod ranker;
+mod related;
+mod search;
+mod session;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod store;
+mod theme;
+
+use alias::AliasEdits;
+use config::Config;
+use editor::LineEditor;
+use emoticon::Emoticons;
+use favorites::Favorites;
+use history::{current_timestamp, History, LogEvent, LogLevel};
+use keymap::{Action, Keymap};
+use instance::{Launch, SingleInstance};
+use index::{find_data_dir, find_emoji, group_name, load_index, EmojiRecord};
+use locale::{emoji_to_code, resolve_locales};
+use query::Query;
+use search::find_fuzzy_match_indices;
 
 slint::include_modules!();
 
-#[derive(Serialize, Deserialize)]
-#[serde(tag = "type")]
-enum LogEvent {
-    #[serde(rename = "keystroke")]
-    Keystroke { ts: u64, key: String },
-    #[serde(rename = "select")]
-    Select { ts: u64, code: String, query: String },
-}
-
-fn get_log_path() -> Option<PathBuf> {
-    dirs::home_dir().map(|h| h.join("emoru_strokes.jsonl"))
-}
-
-fn log_event(event: &LogEvent) {
-    if let Some(path) = get_log_path() {
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&path)
-        {
-            if let Ok(json) = serde_json::to_string(event) {
-                let _ = writeln!(file, "{}", json);
-            }
-        }
-    }
-}
-
-fn current_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0)
-}
-
-/// A recorded emoji selection with query context
-#[derive(Clone)]
-struct Selection {
-    code: String,
-    query: String,
-    ts: u64,
-}
-
-/// Load all selections from the log file
-fn load_selections() -> Vec<Selection> {
-    let mut selections = Vec::new();
-
-    if let Some(path) = get_log_path() {
-        if let Ok(file) = fs::File::open(&path) {
-            let reader = BufReader::new(file);
-            for line in reader.lines().map_while(Result::ok) {
-                if let Ok(event) = serde_json::from_str::<LogEvent>(&line) {
-                    if let LogEvent::Select { ts, code, query } = event {
-                        selections.push(Selection { code, query: query.to_lowercase(), ts });
-                    }
-                }
-            }
-        }
-    }
-
-    selections
-}
-
-/// Check if two queries are prefix-related (one is prefix of the other)
-fn queries_match(current: &str, stored: &str) -> bool {
-    current.starts_with(stored) || stored.starts_with(current)
-}
-
-/// Calculate frecency scores for a given query, considering query-prefix matching
-fn compute_frecency_for_query(selections: &[Selection], current_query: &str) -> HashMap<String, f64> {
-    let mut scores: HashMap<String, f64> = HashMap::new();
-    let now = current_timestamp();
-    let half_life_secs: f64 = 7.0 * 24.0 * 60.0 * 60.0; // 7 days
-
-    let current_lower = current_query.to_lowercase();
-
-    for sel in selections {
-        // Only count selections where the stored query is prefix-related to current query
-        if current_lower.is_empty() || queries_match(&current_lower, &sel.query) {
-            let age_secs = (now.saturating_sub(sel.ts)) as f64;
-            let decay = 0.5_f64.powf(age_secs / half_life_secs);
-            *scores.entry(sel.code.clone()).or_insert(0.0) += decay;
-        }
-    }
-
-    scores
-}
-
 const NUM_SLOTS: usize = 5;
 
-/// Check if a search term matches a word using fuzzy prefix matching:
-/// - First char of term must match first char of word
-/// - Remaining chars must appear in order (subsequence) in the word
-fn term_matches_word(term: &str, word: &str) -> bool {
-    let mut term_chars = term.chars();
-    let mut word_chars = word.chars();
-
-    // First char must match word start
-    match (term_chars.next(), word_chars.next()) {
-        (Some(tc), Some(wc)) if tc == wc => {}
-        (None, _) => return true, // empty term matches everything
-        _ => return false,
-    }
-
-    // Remaining chars must appear in order (subsequence)
-    for tc in term_chars {
-        loop {
-            match word_chars.next() {
-                Some(wc) if wc == tc => break,
-                Some(_) => continue,
-                None => return false,
-            }
-        }
-    }
-
-    true
-}
-
-/// Check if an emoji entry matches all search terms
-/// Each term must match at least one word in the description
-fn entry_matches_terms(entry: &str, terms: &[&str]) -> bool {
-    let parts: Vec<&str> = entry.split("| ").collect();
-    if parts.len() < 2 {
-        return false;
-    }
-    let description = parts[1].to_lowercase();
-    let words: Vec<&str> = description.split_whitespace().collect();
-
-    // Each term must match at least one word
-    terms.iter().all(|term| {
-        if term.is_empty() {
-            return true;
-        }
-        words.iter().any(|word| term_matches_word(term, word))
-    })
-}
-
-/// Find character indices that match a term using fuzzy subsequence matching
-/// Returns None if no match, or Some(indices) of matched characters in word
-fn find_fuzzy_match_indices(term: &str, word: &str) -> Option<Vec<usize>> {
-    let mut term_chars = term.chars().peekable();
-    let mut indices = Vec::new();
-
-    // First char must match word start
-    let first_term = term_chars.next()?;
-    let mut word_iter = word.char_indices();
-    let (first_idx, first_word) = word_iter.next()?;
-
-    if first_term.to_lowercase().next()? != first_word.to_lowercase().next()? {
-        return None;
-    }
-    indices.push(first_idx);
-
-    // Remaining chars must appear in order
-    for tc in term_chars {
-        let tc_lower = tc.to_lowercase().next()?;
-        loop {
-            match word_iter.next() {
-                Some((idx, wc)) => {
-                    if wc.to_lowercase().next()? == tc_lower {
-                        indices.push(idx);
-                        break;
-                    }
-                }
-                None => return None,
-            }
-        }
-    }
+/// Hints shown in the rows that have no result
+const EMPTY_ROW_HINTS: [&str; NUM_SLOTS] =
+    ["Start typing to match emotes!", "", "Press Escape to exit", "Hit Return to choose.", "Ctrl-Backspace to empty"];
 
-    Some(indices)
-}
+/// Results kept for scrolling beyond the visible rows
+const MAX_RESULTS: usize = 100;
 
 /// Build text segments with highlighted (bold) matches for fuzzy prefix matching
-fn build_highlight_segments(text: &str, terms: &[&str]) -> Vec<TextSegment> {
+fn build_highlight_segments(text: &str, terms: &[String]) -> Vec<TextSegment> {
     if terms.is_empty() || terms.iter().all(|t| t.is_empty()) {
         return vec![TextSegment {
             text: SharedString::from(text),
@@ -201,14 +79,13 @@ fn build_highlight_segments(text: &str, terms: &[&str]) -> Vec<TextSegment> {
         // Find actual position of word in text
         if let Some(pos) = text[word_start..].find(word) {
             let abs_start = word_start + pos;
-            let word_lower = word.to_lowercase();
 
             // Check each term against this word
             for term in terms {
                 if term.is_empty() {
                     continue;
                 }
-                if let Some(indices) = find_fuzzy_match_indices(term, &word_lower) {
+                if let Some(indices) = find_fuzzy_match_indices(term, word) {
                     // Mark these character positions as highlighted
                     for idx in indices {
                         let abs_idx = abs_start + idx;
@@ -266,152 +143,290 @@ fn build_highlight_segments(text: &str, terms: &[&str]) -> Vec<TextSegment> {
     segments
 }
 
-/// Find the data directory containing emoji files.
-/// Searches in order:
-/// 1. ./data (next to executable)
-/// 2. ../data (for development)
-/// 3. ~/.emoru/data
-/// 4. ~/emoji_picker_images (legacy)
-fn find_data_dir() -> Option<PathBuf> {
-    // Next to executable
-    if let Ok(exe) = std::env::current_exe() {
-        let exe_dir = exe.parent().unwrap_or(&exe);
-        let data_dir = exe_dir.join("data");
-        if data_dir.exists() {
-            return Some(data_dir);
-        }
-    }
-
-    // Development: ./data
-    let local_data = PathBuf::from("data");
-    if local_data.exists() {
-        return Some(local_data);
-    }
-
-    // User config: ~/.emoru/data
-    if let Some(home) = dirs::home_dir() {
-        let user_data = home.join(".emoru").join("data");
-        if user_data.exists() {
-            return Some(user_data);
-        }
-
-        // Legacy location
-        let legacy = home.join("emoji_picker_images");
-        if legacy.exists() {
-            // For legacy, return parent since we expect data/emoji_picker_images structure
-            return Some(home.clone());
-        }
-    }
-
-    None
-}
-
 struct AppState {
-    emojis: Vec<String>,
-    letters: Vec<char>,
-    matches: Vec<String>,
+    emojis: Vec<EmojiRecord>,
+    input: LineEditor,
+    matches: Vec<EmojiRecord>,
+    /// Highlighted result, counted from the first match
     selected_index: i32,
+    /// First match shown in the visible rows
+    scroll: usize,
     selected_emoji: Option<String>,
     image_cache: HashMap<String, Image>,
     data_dir: Option<PathBuf>,
-    selections: Vec<Selection>,
+    locales: Vec<String>,
+    /// Last successfully parsed query, kept while the current input has a syntax error
+    query: Query,
+    query_error: Option<String>,
+    emoticons: Emoticons,
+    emoticon_mode: bool,
+    /// Emoji often used with the previous selection, shown in a side row
+    related: Vec<EmojiRecord>,
+    related_anchor: Option<EmojiRecord>,
+    /// Highlighted related emoji, or -1 when the highlight is in the results
+    related_index: i32,
+    history: History,
+    favorites: Favorites,
+    /// The application the picker was opened from, recorded with selections
+    app: Option<String>,
+    alias_threshold: u32,
+    /// Whether the details panel for the highlighted emoji is shown
+    preview: bool,
+    /// Kept open so text copied from the row menu stays available while the picker runs
+    clipboard: Option<Clipboard>,
+    keymap: Keymap,
 }
 
 impl AppState {
-    fn new() -> Self {
+    fn new(config: &Config, log_level: LogLevel, app: Option<String>) -> Self {
+        let store = store::open(config.storage)
+            .map_err(|e| eprintln!("emoru: history unavailable: {}", e))
+            .ok();
+        let history = History::new(
+            log_level,
+            store,
+            ranker::configured(&config.ranking),
+            app.clone(),
+            config.app_weight,
+        );
         Self {
             emojis: Vec::new(),
-            letters: Vec::new(),
+            input: LineEditor::default(),
             matches: Vec::new(),
             selected_index: 0,
+            scroll: 0,
             selected_emoji: None,
             image_cache: HashMap::new(),
             data_dir: find_data_dir(),
-            selections: load_selections(),
+            locales: resolve_locales(config),
+            query: Query::default(),
+            query_error: None,
+            emoticons: Emoticons::new(&config.emoticons),
+            emoticon_mode: config.emoticon_mode,
+            related: Vec::new(),
+            related_anchor: None,
+            related_index: -1,
+            history,
+            favorites: Favorites::load(),
+            app,
+            alias_threshold: config.alias_threshold,
+            preview: config.preview,
+            clipboard: None,
+            keymap: Keymap::new(&config.keymap, &config.keybindings, config.digit_select),
         }
     }
 
-    /// Get the emoji code from an entry string
-    fn get_code(entry: &str) -> Option<String> {
-        let parts: Vec<&str> = entry.split("| ").collect();
-        if parts.len() >= 3 {
-            Some(parts[2].to_string())
-        } else {
-            None
+    fn load_emojis(&mut self) {
+        if self.emojis.is_empty() {
+            self.emojis = load_index(self.data_dir.as_deref(), &self.locales);
+            let counts = self.history.query_counts();
+            let aliases = alias::aliases(&counts, &self.emojis, &AliasEdits::load(), self.alias_threshold);
+            alias::apply(&mut self.emojis, &aliases);
         }
     }
 
-    fn load_emojis(&mut self) {
-        if self.emojis.is_empty() {
-            // Try bundled data first
-            if let Some(ref data_dir) = self.data_dir {
-                let path = data_dir.join("emojis9.txt");
-                if let Ok(content) = fs::read_to_string(&path) {
-                    self.emojis = content.lines().map(String::from).collect();
-                    return;
-                }
-            }
+    /// Suggest emoji often used with the previous pick, if it was made moments ago
+    fn load_related(&mut self) {
+        let Some((recent, related)) = self.history.recent_related(NUM_SLOTS) else {
+            return;
+        };
+        self.related = related
+            .into_iter()
+            .filter_map(|(code, _)| self.emojis.iter().find(|r| r.code == code).cloned())
+            .collect();
+        if !self.related.is_empty() {
+            self.related_anchor = self.emojis.iter().find(|r| r.code == recent).cloned();
+        }
+    }
 
-            // Fallback to home directory (legacy)
-            if let Some(home) = dirs::home_dir() {
-                let path = home.join("emojis9.txt");
-                if let Ok(content) = fs::read_to_string(&path) {
-                    self.emojis = content.lines().map(String::from).collect();
-                }
-            }
+    /// The entry that Enter would pick: a highlighted related emoji, or the highlighted result
+    fn highlighted_entry(&self) -> Option<&EmojiRecord> {
+        if self.related_index >= 0 {
+            self.related.get(self.related_index as usize)
+        } else {
+            self.matches.get(self.selected_index as usize)
         }
     }
 
     fn search_text(&self) -> String {
-        self.letters.iter().collect()
+        self.input.text()
     }
 
     fn search(&mut self) {
-        let query: String = self.letters.iter().collect::<String>().to_lowercase();
-        let terms: Vec<&str> = query.split_whitespace().collect();
+        let query = self.search_text().to_lowercase();
+        let emoticon_match = self.emoticon_match();
+
+        // Keep the previous results on a syntax error so they don't flicker away mid-typing
+        match Query::parse(&query) {
+            Ok(parsed) => {
+                self.query = parsed;
+                self.query_error = None;
+            }
+            // An emoticon is shown even when it isn't a valid query
+            Err(_) if emoticon_match.is_some() => {
+                self.query = Query::default();
+                self.query_error = None;
+            }
+            Err(e) => {
+                self.query_error = Some(e.to_string());
+                return;
+            }
+        }
 
         // Compute frecency scores based on current query prefix
-        let frecency = compute_frecency_for_query(&self.selections, &query);
-
-        // Helper to get frecency for an entry
-        let get_frecency = |entry: &str| -> f64 {
-            if let Some(code) = Self::get_code(entry) {
-                *frecency.get(&code).unwrap_or(&0.0)
-            } else {
-                0.0
+        let frecency = self.history.frecency(&query);
+
+        self.matches = search::results(
+            &self.emojis,
+            &self.query,
+            &frecency,
+            &self.favorites,
+            emoticon_match.as_ref(),
+        )
+        .into_iter()
+        .take(MAX_RESULTS)
+        .cloned()
+        .collect();
+
+        // Ensure selected_index is within bounds
+        let max_idx = (self.matches.len() as i32 - 1).max(0);
+        self.selected_index = self.selected_index.min(max_idx);
+        self.keep_visible();
+    }
+
+    /// Move the highlight through the results by some rows
+    fn move_selection(&mut self, rows: i32) {
+        self.related_index = -1;
+        let max_idx = (self.matches.len() as i32 - 1).max(0);
+        self.selected_index = (self.selected_index + rows).clamp(0, max_idx);
+        self.keep_visible();
+    }
+
+    /// Run a keymap action; selecting and closing are left to the caller
+    fn run(&mut self, action: Action) {
+        match action {
+            Action::Select | Action::Close | Action::SelectRow(_) => {}
+            Action::Next => self.move_selection(1),
+            Action::Prev => self.move_selection(-1),
+            Action::PageDown => self.move_selection(NUM_SLOTS as i32),
+            Action::PageUp => self.move_selection(-(NUM_SLOTS as i32)),
+            // Left/Right choose a related emoji until something is typed
+            Action::Left if self.input.is_empty() => {
+                self.related_index = (self.related_index - 1).max(-1);
+            }
+            Action::Right if self.input.is_empty() => {
+                let max_idx = self.related.len() as i32 - 1;
+                self.related_index = (self.related_index + 1).min(max_idx);
             }
+            Action::Left => self.input.left(),
+            Action::Right => self.input.right(),
+            Action::WordLeft => self.input.word_left(),
+            Action::WordRight => self.input.word_right(),
+            Action::LineStart => self.input.home(),
+            Action::LineEnd => self.input.end(),
+            Action::DeleteBack => self.input.delete_back(),
+            Action::DeleteForward => self.input.delete_forward(),
+            Action::DeleteWordBack => self.input.delete_word_back(),
+            Action::DeleteWordForward => self.input.delete_word_forward(),
+            Action::KillToStart => self.input.kill_to_start(),
+            Action::KillToEnd => self.input.kill_to_end(),
+            Action::Clear => self.input.clear(),
+            Action::Paste => self.paste(),
+            Action::Pin => self.toggle_favorite(false),
+            Action::Hide => self.toggle_favorite(true),
+            Action::TogglePreview => self.toggle_preview(),
+        }
+    }
+
+    /// Scroll the results so the highlighted one is in the visible rows
+    fn keep_visible(&mut self) {
+        let selected = self.selected_index.max(0) as usize;
+        if selected < self.scroll {
+            self.scroll = selected;
+        } else if selected >= self.scroll + NUM_SLOTS {
+            self.scroll = selected + 1 - NUM_SLOTS;
+        }
+        self.scroll = self.scroll.min(self.matches.len().saturating_sub(NUM_SLOTS));
+    }
+
+    /// Scroll the results by some rows, keeping the highlight in the visible rows
+    fn scroll_by(&mut self, rows: i32) {
+        let max_scroll = self.matches.len().saturating_sub(NUM_SLOTS);
+        self.scroll = self.scroll.saturating_add_signed(rows as isize).min(max_scroll);
+        let last_visible = (self.scroll + NUM_SLOTS).min(self.matches.len()).saturating_sub(1);
+        self.selected_index = self.selected_index.clamp(self.scroll as i32, last_visible as i32);
+    }
+
+    /// Highlight the result in a visible row, e.g. under the mouse
+    fn highlight_row(&mut self, row: i32) {
+        let index = self.scroll as i32 + row;
+        if (index as usize) < self.matches.len() {
+            self.related_index = -1;
+            self.selected_index = index;
+        }
+    }
+
+    /// Copy the highlighted entry's codepoints (`U+1F389`) to the clipboard
+    fn copy_codepoint(&mut self) {
+        let Some(text) = self.highlighted_entry().map(|e| format!("U+{}", emoji_to_code(&e.emoji))) else {
+            return;
         };
+        if let Some(clipboard) = self.clipboard() {
+            if let Err(e) = clipboard.set_text(text) {
+                eprintln!("emoru: could not copy: {}", e);
+            }
+        }
+    }
 
-        // Filter matching emojis using fuzzy prefix matching
-        let mut filtered: Vec<String> = self.emojis
-            .iter()
-            .filter(|e| entry_matches_terms(e, &terms))
-            .cloned()
-            .collect();
+    /// Insert the clipboard text at the cursor
+    fn paste(&mut self) {
+        if let Some(text) = self.clipboard().and_then(|c| c.get_text().ok()) {
+            self.input.insert(&text);
+        }
+    }
 
-        // Sort by frecency (highest first)
-        filtered.sort_by(|a, b| {
-            let fa = get_frecency(a);
-            let fb = get_frecency(b);
-            fb.partial_cmp(&fa).unwrap_or(std::cmp::Ordering::Equal)
-        });
+    /// The clipboard, opened on first use
+    fn clipboard(&mut self) -> Option<&mut Clipboard> {
+        if self.clipboard.is_none() {
+            self.clipboard = Clipboard::new().map_err(|e| eprintln!("emoru: clipboard unavailable: {}", e)).ok();
+        }
+        self.clipboard.as_mut()
+    }
 
-        self.matches = filtered.into_iter().take(NUM_SLOTS).collect();
+    /// Pin or unpin (`hide` false), or hide or unhide, the highlighted entry
+    /// and save the favorites
+    fn toggle_favorite(&mut self, hide: bool) {
+        let Some(code) = self.highlighted_entry().map(|e| e.code.clone()) else {
+            return;
+        };
+        if hide {
+            self.favorites.toggle_hide(&code);
+        } else {
+            self.favorites.toggle_pin(&code);
+        }
+        if let Err(e) = self.favorites.save() {
+            eprintln!("emoru: could not save favorites: {}", e);
+        }
+        self.search();
+    }
 
-        if self.matches.is_empty() && !self.emojis.is_empty() {
-            // Show top frecency emojis when no query (all selections count)
-            let mut top: Vec<String> = self.emojis.clone();
-            top.sort_by(|a, b| {
-                let fa = get_frecency(a);
-                let fb = get_frecency(b);
-                fb.partial_cmp(&fa).unwrap_or(std::cmp::Ordering::Equal)
-            });
-            self.matches = top.into_iter().take(NUM_SLOTS).collect();
+    /// Show or hide the preview panel and remember the choice in the config
+    fn toggle_preview(&mut self) {
+        self.preview = !self.preview;
+        if let Err(e) = config::save_setting("preview", serde_json::Value::Bool(self.preview)) {
+            eprintln!("emoru: could not save the preview setting: {}", e);
         }
+    }
 
-        // Ensure selected_index is within bounds
-        let max_idx = (self.matches.len() as i32 - 1).max(0);
-        self.selected_index = self.selected_index.min(max_idx);
+    /// The index entry for the emoticon typed as the whole query, if any.
+    /// Uses the raw query since emoticons are case-sensitive (`:P` vs `:p`).
+    fn emoticon_match(&self) -> Option<EmojiRecord> {
+        if !self.emoticon_mode {
+            return None;
+        }
+        let raw = self.search_text();
+        let emoji = self.emoticons.get(raw.trim())?;
+        find_emoji(&self.emojis, emoji).cloned()
     }
 
     fn load_image(&mut self, code: &str) -> Option<Image> {
@@ -453,51 +468,110 @@ impl AppState {
     }
 
     fn get_emoji_entries(&mut self) -> Vec<EmojiEntry> {
-        let mut entries = Vec::new();
-        let query: String = self.letters.iter().collect::<String>().to_lowercase();
-        let terms: Vec<&str> = query.split_whitespace().collect();
-
-        for entry in &self.matches.clone() {
-            let parts: Vec<&str> = entry.split("| ").collect();
-            if parts.len() >= 3 {
-                let emoji = parts[0].to_string();
-                let description = parts[1].to_string();
-                let code = parts[2].to_string();
-                let image_data = self.load_image(&code).unwrap_or_default();
-
-                // Build segments with multi-term highlighting
-                let segments = build_highlight_segments(&description, &terms);
-
-                entries.push(EmojiEntry {
-                    emoji: SharedString::from(emoji),
-                    description: SharedString::from(description),
-                    segments: ModelRc::from(Rc::new(VecModel::from(segments))),
-                    image_data,
-                });
-            } else {
-                entries.push(EmojiEntry {
-                    emoji: SharedString::default(),
-                    description: SharedString::default(),
-                    segments: ModelRc::default(),
-                    image_data: Image::default(),
-                });
-            }
+        let terms = self.query.highlight_terms();
+        let visible: Vec<EmojiRecord> = self.matches.iter().skip(self.scroll).take(NUM_SLOTS).cloned().collect();
+        visible.iter().map(|entry| self.build_emoji_entry(entry, &terms)).collect()
+    }
+
+    fn get_related_entries(&mut self) -> Vec<EmojiEntry> {
+        self.related
+            .clone()
+            .iter()
+            .map(|entry| self.build_emoji_entry(entry, &[]))
+            .collect()
+    }
+
+    fn build_emoji_entry(&mut self, entry: &EmojiRecord, terms: &[String]) -> EmojiEntry {
+        let image_data = self.load_image(&entry.code).unwrap_or_default();
+
+        // Build segments with multi-term highlighting
+        let segments = build_highlight_segments(&entry.description, terms);
+
+        EmojiEntry {
+            emoji: SharedString::from(&entry.emoji),
+            description: SharedString::from(&entry.description),
+            segments: ModelRc::from(Rc::new(VecModel::from(segments))),
+            image_data,
+            pinned: self.favorites.is_pinned(&entry.code),
+            hidden: self.favorites.is_hidden(&entry.code),
         }
+    }
 
-        entries
+    /// Details of the highlighted entry for the preview panel, empty when nothing is highlighted
+    fn build_preview(&mut self) -> EmojiDetails {
+        let Some(entry) = self.highlighted_entry().cloned() else {
+            return EmojiDetails::default();
+        };
+        let codepoints: Vec<String> = entry.emoji.chars().map(|c| format!("U+{:04X}", c as u32)).collect();
+        let (name, info, shortcodes, variants) = match entry.meta {
+            Some(meta) => {
+                let version = meta.unicode_version();
+                let info = format!("Unicode {}.{} · {}", version.major(), version.minor(), group_name(meta.group()));
+                let shortcodes: Vec<String> = meta.shortcodes().map(|s| format!(":{}:", s)).collect();
+                // Skin tones other than the emoji itself, with an image in the index
+                let codes: Vec<String> = meta
+                    .skin_tones()
+                    .into_iter()
+                    .flatten()
+                    .filter(|tone| tone.as_str() != meta.as_str())
+                    .filter_map(|tone| find_emoji(&self.emojis, tone.as_str()).map(|r| r.code.clone()))
+                    .collect();
+                let variants: Vec<Image> = codes.iter().filter_map(|code| self.load_image(code)).collect();
+                (meta.name().to_string(), info, shortcodes.join(" "), variants)
+            }
+            None => (entry.description.clone(), String::new(), String::new(), Vec::new()),
+        };
+
+        EmojiDetails {
+            image_data: self.load_image(&entry.code).unwrap_or_default(),
+            name: SharedString::from(name),
+            codepoints: SharedString::from(codepoints.join(" ")),
+            info: SharedString::from(info),
+            shortcodes: SharedString::from(shortcodes),
+            keywords: SharedString::from(entry.keywords.join(", ")),
+            aliases: SharedString::from(entry.aliases.join(", ")),
+            variants: ModelRc::from(Rc::new(VecModel::from(variants))),
+        }
     }
 }
 
 fn main() -> Result<(), slint::PlatformError> {
+    let args = cli::Cli::parse();
+    if let Some(command) = args.command {
+        if let Err(e) = cli::run(command) {
+            eprintln!("emoru: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let config = Config::load();
+    let lock = match instance::acquire(config.single_instance) {
+        Ok(Launch::Open(lock)) => lock,
+        Ok(Launch::HandedOver) => return Ok(()),
+        Err(e) => {
+            eprintln!("emoru: single-instance lock unavailable: {}", e);
+            None
+        }
+    };
+
+    // Before our own window takes focus
+    let active_app = active_app::detect(&config.app_detection);
+    store::spawn_auto_compaction(config.storage, config.retention_days * 24 * 60 * 60);
+
     // Suppress Qt warnings (including thread cleanup warnings)
     std::env::set_var("QT_LOGGING_RULES", "*=false");
     std::env::set_var("QT_MESSAGE_PATTERN", "");
 
     let app = EmojiPicker::new()?;
-    let state = Rc::new(RefCell::new(AppState::new()));
+    apply_theme(&app, &theme::load(&config.theme));
+    app.set_close_on_focus_loss(config.close_on_focus_loss);
+    let log_level = if args.incognito { LogLevel::Off } else { config.logging };
+    let state = Rc::new(RefCell::new(AppState::new(&config, log_level, active_app)));
 
     // Load emojis on startup
     state.borrow_mut().load_emojis();
+    state.borrow_mut().load_related();
     state.borrow_mut().search();
 
     // Initial update
@@ -509,44 +583,99 @@ fn main() -> Result<(), slint::PlatformError> {
     app.on_key_pressed(move |key| {
         let mut state = state_clone.borrow_mut();
         let key_str = key.as_str();
+        let action = state.keymap.action(key_str);
+
+        // Selecting and closing act on the window below; a row picked by
+        // number is highlighted first, so it is selected and logged like Enter
+        if matches!(action, Some(Action::Select | Action::Close | Action::SelectRow(_))) {
+            if let Some(Action::SelectRow(row)) = action {
+                if row >= state.matches.len().saturating_sub(state.scroll).min(NUM_SLOTS) {
+                    return;
+                }
+                state.highlight_row(row as i32);
+            }
+            let has_entry = state.highlighted_entry().is_some();
+            drop(state);
+            if let Some(app) = app_weak.upgrade() {
+                if action == Some(Action::Close) {
+                    app.invoke_close_requested();
+                } else if has_entry {
+                    app.invoke_emoji_selected(SharedString::default());
+                }
+            }
+            return;
+        }
 
         // Log keystroke
-        log_event(&LogEvent::Keystroke {
+        state.history.record(&LogEvent::Keystroke {
             ts: current_timestamp(),
             key: key_str.to_string(),
         });
 
-        match key_str {
-            "up" => {
-                state.selected_index = (state.selected_index - 1).max(0);
-            }
-            "down" => {
-                let max_idx = (state.matches.len() as i32 - 1).max(0);
-                state.selected_index = (state.selected_index + 1).min(max_idx);
-            }
-            "backspace" => {
-                state.letters.pop();
-                state.selected_index = 0;
-                state.search();
-            }
-            "ctrl-backspace" => {
-                state.letters.clear();
-                state.selected_index = 0;
-                state.search();
-            }
-            "shift" => {
-                // Swallow shift key, don't output anything
-            }
-            _ => {
-                // Regular character
-                for c in key_str.chars() {
-                    state.letters.push(c);
+        let text_before = state.search_text();
+        match action {
+            Some(action) => state.run(action),
+            None => {
+                if let Some(text) = keymap::typed_text(key_str) {
+                    state.input.insert(text);
                 }
-                state.selected_index = 0;
-                state.search();
             }
         }
 
+        if state.search_text() != text_before {
+            state.selected_index = 0;
+            state.search();
+        }
+
+        drop(state);
+        if let Some(app) = app_weak.upgrade() {
+            update_ui(&app, &state_clone);
+        }
+    });
+
+    // Handle the mouse: hovering highlights, a click selects through emoji_selected
+    let app_weak = app.as_weak();
+    let state_clone = state.clone();
+    app.on_row_hovered(move |row| {
+        state_clone.borrow_mut().highlight_row(row);
+        if let Some(app) = app_weak.upgrade() {
+            update_ui(&app, &state_clone);
+        }
+    });
+
+    let app_weak = app.as_weak();
+    let state_clone = state.clone();
+    app.on_related_hovered(move |index| {
+        let mut state = state_clone.borrow_mut();
+        if (index as usize) < state.related.len() {
+            state.related_index = index;
+        }
+        drop(state);
+        if let Some(app) = app_weak.upgrade() {
+            update_ui(&app, &state_clone);
+        }
+    });
+
+    let app_weak = app.as_weak();
+    let state_clone = state.clone();
+    app.on_scrolled(move |rows| {
+        state_clone.borrow_mut().scroll_by(rows);
+        if let Some(app) = app_weak.upgrade() {
+            update_ui(&app, &state_clone);
+        }
+    });
+
+    let app_weak = app.as_weak();
+    let state_clone = state.clone();
+    app.on_row_action(move |row, action| {
+        let mut state = state_clone.borrow_mut();
+        state.highlight_row(row);
+        match action.as_str() {
+            "copy-codepoint" => state.copy_codepoint(),
+            "pin" => state.toggle_favorite(false),
+            "hide" => state.toggle_favorite(true),
+            _ => {}
+        }
         drop(state);
         if let Some(app) = app_weak.upgrade() {
             update_ui(&app, &state_clone);
@@ -560,24 +689,22 @@ fn main() -> Result<(), slint::PlatformError> {
         let mut state = state_clone.borrow_mut();
 
         // Log selection with code and query
-        let query: String = state.letters.iter().collect();
-        let idx = state.selected_index as usize;
-        if let Some(entry) = state.matches.get(idx) {
-            let parts: Vec<&str> = entry.split("| ").collect();
-            if parts.len() >= 3 {
-                let code = parts[2].to_string();
-                log_event(&LogEvent::Select {
-                    ts: current_timestamp(),
-                    code,
-                    query,
-                });
-            }
+        let query = state.search_text();
+        let mut emoji = emoji.to_string();
+        if let Some(entry) = state.highlighted_entry() {
+            state.history.record(&LogEvent::Select {
+                ts: current_timestamp(),
+                code: entry.code.clone(),
+                query,
+                app: state.app.clone(),
+            });
+            emoji = entry.emoji.clone();
         }
 
-        state.selected_emoji = Some(emoji.to_string());
+        state.selected_emoji = Some(emoji);
         drop(state);
         if let Some(app) = app_weak.upgrade() {
-            app.hide().ok();
+            close(&app);
         }
     });
 
@@ -585,11 +712,32 @@ fn main() -> Result<(), slint::PlatformError> {
     let app_weak = app.as_weak();
     app.on_close_requested(move || {
         if let Some(app) = app_weak.upgrade() {
-            app.hide().ok();
+            close(&app);
         }
     });
 
-    app.run()?;
+    // Later launches close the picker or bring it to the front
+    if let Some(lock) = &lock {
+        let app_weak = app.as_weak();
+        let listening = lock.listen(move |request| {
+            let _ = app_weak.upgrade_in_event_loop(move |app| match request {
+                SingleInstance::Toggle => app.invoke_close_requested(),
+                SingleInstance::Focus => focus(&app),
+                SingleInstance::Off => {}
+            });
+        });
+        if let Err(e) = listening {
+            eprintln!("emoru: cannot listen for other launches: {}", e);
+        }
+    }
+
+    // Place the window once it exists, so its scale factor is known
+    app.show()?;
+    place_window(&app, config.placement);
+    // Runs until `close`; hiding the window alone doesn't end it, so `focus` can show it again
+    slint::run_event_loop_until_quit()?;
+    // Let the next launch open a picker while this one pastes
+    drop(lock);
 
     // After window closes, paste the emoji if one was selected
     if let Some(emoji) = state.borrow().selected_emoji.clone() {
@@ -602,12 +750,97 @@ fn main() -> Result<(), slint::PlatformError> {
 fn update_ui(app: &EmojiPicker, state: &Rc<RefCell<AppState>>) {
     let mut state = state.borrow_mut();
 
-    app.set_search_text(SharedString::from(state.search_text()));
-    app.set_selected_index(state.selected_index);
+    let (before_cursor, after_cursor) = state.input.split();
+    app.set_search_before_cursor(SharedString::from(before_cursor));
+    app.set_search_after_cursor(SharedString::from(after_cursor));
+    app.set_query_error(SharedString::from(state.query_error.clone().unwrap_or_default()));
+    app.set_selected_index(state.selected_index - state.scroll as i32);
 
     let entries = state.get_emoji_entries();
+    let hints: Vec<SharedString> =
+        EMPTY_ROW_HINTS[entries.len().min(NUM_SLOTS)..].iter().map(|h| (*h).into()).collect();
+    app.set_empty_row_hints(ModelRc::from(Rc::new(VecModel::from(hints))));
     let model = Rc::new(VecModel::from(entries));
     app.set_emoji_entries(ModelRc::from(model));
+
+    let related = state.get_related_entries();
+    app.set_related_entries(ModelRc::from(Rc::new(VecModel::from(related))));
+    app.set_related_index(state.related_index);
+    if let Some(anchor) = state.related_anchor.clone() {
+        let image = state.load_image(&anchor.code).unwrap_or_default();
+        app.set_related_anchor(image);
+    }
+
+    app.set_preview_visible(state.preview);
+    if state.preview {
+        let preview = state.build_preview();
+        app.set_preview(preview);
+    }
+}
+
+/// Hide the window and end the picker
+fn close(app: &EmojiPicker) {
+    app.hide().ok();
+    slint::quit_event_loop().ok();
+}
+
+/// Raise the picker and give it the keyboard focus
+fn focus(app: &EmojiPicker) {
+    if app.get_window_active() {
+        return;
+    }
+    let window = app.window();
+    window.set_minimized(false);
+    if window.has_winit_window() {
+        window.with_winit_window(|window| window.focus_window());
+    } else {
+        // Other backends can't raise a window, but window managers activate one shown again
+        app.hide().ok();
+        app.show().ok();
+    }
+}
+
+/// Widest the results may be, in logical pixels
+const MAX_LIST_WIDTH: f32 = 1200.0;
+
+/// Limit the window width to the monitor it opens on and move it there
+fn place_window(app: &EmojiPicker, placement: placement::Placement) {
+    let Some(screen) = placement::provider() else {
+        return;
+    };
+    let window = app.window();
+    let scale = window.scale_factor();
+    // Positions are in physical pixels on X11 and logical points on macOS
+    let units = if screen.physical() { scale } else { 1.0 };
+    let preview_width = if app.get_preview_visible() { app.get_preview_width() } else { 0.0 };
+
+    let position = placement::place(placement, screen.as_ref(), |monitor| {
+        let available = monitor.width as f32 / units - preview_width - 16.0;
+        let max_list_width = available.clamp(app.get_min_list_width(), MAX_LIST_WIDTH);
+        app.set_max_list_width(max_list_width);
+        placement::Size {
+            width: ((app.get_list_width() + preview_width) * units) as i32,
+            max_width: ((max_list_width + preview_width) * units) as i32,
+            height: (app.get_content_height() * units) as i32,
+        }
+    });
+    match position {
+        Some(p) if screen.physical() => window.set_position(slint::PhysicalPosition::new(p.x, p.y)),
+        Some(p) => window.set_position(slint::LogicalPosition::new(p.x as f32, p.y as f32)),
+        None => {}
+    }
+}
+
+fn apply_theme(app: &EmojiPicker, theme: &theme::Theme) {
+    let global = app.global::<Theme>();
+    global.set_background(theme.background);
+    global.set_text(theme.text);
+    global.set_muted(theme.muted);
+    global.set_hint(theme.hint);
+    global.set_selection(theme.selection);
+    global.set_separator(theme.separator);
+    global.set_error(theme.error);
+    global.set_font_family(SharedString::from(&theme.font_family));
 }
 
 fn paste_emoji(emoji: &str) {
@@ -0,0 +1,209 @@
+use serde::Deserialize;
+
+use crate::active_app::output;
+
+/// Where the picker window opens
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    /// Centered on the monitor with the mouse pointer
+    #[default]
+    Center,
+    /// At the mouse pointer
+    Pointer,
+    /// Below the text caret of the focused application, where it can be
+    /// found (AT-SPI on Linux); otherwise at the pointer
+    Caret,
+    /// Wherever the window manager puts it
+    System,
+}
+
+#[derive(Clone, Copy)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    fn contains(&self, point: Point) -> bool {
+        (self.x..self.x + self.width).contains(&point.x) && (self.y..self.y + self.height).contains(&point.y)
+    }
+}
+
+/// The window's size on a monitor, in the provider's units: its width can
+/// grow up to `max_width` after it opens
+#[derive(Clone, Copy)]
+pub struct Size {
+    pub width: i32,
+    pub max_width: i32,
+    pub height: i32,
+}
+
+/// Space left between the window and the monitor edges or the caret
+const MARGIN: i32 = 8;
+
+/// Pointer, monitor and caret positions on the desktop
+pub trait ScreenProvider {
+    /// Whether positions are in physical pixels (X11) rather than logical points (macOS)
+    fn physical(&self) -> bool;
+    fn pointer(&self) -> Option<Point>;
+    fn monitors(&self) -> Vec<Rect>;
+    /// Bounds of the text caret in the focused application
+    fn caret(&self) -> Option<Rect> {
+        None
+    }
+}
+
+pub struct X11;
+
+impl ScreenProvider for X11 {
+    fn physical(&self) -> bool {
+        true
+    }
+
+    fn pointer(&self) -> Option<Point> {
+        // `X=812\nY=440\nSCREEN=0\nWINDOW=65011719`
+        let location = output("xdotool", &["getmouselocation", "--shell"])?;
+        let value = |name: &str| {
+            location.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix('=')?.parse().ok())
+        };
+        Some(Point { x: value("X")?, y: value("Y")? })
+    }
+
+    fn monitors(&self) -> Vec<Rect> {
+        // `HDMI-1 connected primary 1920x1080+0+0 (normal left inverted ...) 527mm x 296mm`
+        let Some(query) = output("xrandr", &["--query"]) else {
+            return Vec::new();
+        };
+        query
+            .lines()
+            .filter(|line| line.contains(" connected"))
+            .filter_map(|line| line.split_whitespace().find_map(parse_geometry))
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn caret(&self) -> Option<Rect> {
+        crate::caret::find()
+    }
+}
+
+/// Parse an X geometry such as `1920x1080+0+0`
+fn parse_geometry(geometry: &str) -> Option<Rect> {
+    let (width, rest) = geometry.split_once('x')?;
+    let (height, rest) = rest.split_once('+')?;
+    let (x, y) = rest.split_once('+')?;
+    Some(Rect { x: x.parse().ok()?, y: y.parse().ok()?, width: width.parse().ok()?, height: height.parse().ok()? })
+}
+
+pub struct MacOs;
+
+/// Prints `pointerX,pointerY;x,y,width,height;...` for the pointer and each
+/// screen, in points from the top left of the main screen
+const MACOS_SCREENS: &str = r#"
+ObjC.import("AppKit");
+var top = $.NSScreen.screens.objectAtIndex(0).frame.size.height;
+var p = $.NSEvent.mouseLocation;
+var out = [Math.round(p.x) + "," + Math.round(top - p.y)];
+for (var i = 0; i < $.NSScreen.screens.count; i++) {
+    var f = $.NSScreen.screens.objectAtIndex(i).frame;
+    out.push([f.origin.x, top - f.origin.y - f.size.height, f.size.width, f.size.height].map(Math.round).join(","));
+}
+out.join(";");
+"#;
+
+impl MacOs {
+    fn screens(&self) -> Option<(Point, Vec<Rect>)> {
+        let text = output("osascript", &["-l", "JavaScript", "-e", MACOS_SCREENS])?;
+        let mut parts = text.split(';').map(|part| part.split(',').filter_map(|v| v.parse().ok()).collect::<Vec<i32>>());
+        let pointer = match parts.next()?[..] {
+            [x, y] => Point { x, y },
+            _ => return None,
+        };
+        let monitors = parts
+            .filter_map(|values| match values[..] {
+                [x, y, width, height] => Some(Rect { x, y, width, height }),
+                _ => None,
+            })
+            .collect();
+        Some((pointer, monitors))
+    }
+}
+
+impl ScreenProvider for MacOs {
+    fn physical(&self) -> bool {
+        false
+    }
+
+    fn pointer(&self) -> Option<Point> {
+        self.screens().map(|(pointer, _)| pointer)
+    }
+
+    fn monitors(&self) -> Vec<Rect> {
+        self.screens().map(|(_, monitors)| monitors).unwrap_or_default()
+    }
+}
+
+/// The provider for this desktop. None on Wayland, where windows can't place themselves.
+pub fn provider() -> Option<Box<dyn ScreenProvider>> {
+    if cfg!(target_os = "macos") {
+        Some(Box::new(MacOs))
+    } else if std::env::var_os("DISPLAY").is_some() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        Some(Box::new(X11))
+    } else {
+        None
+    }
+}
+
+/// The monitor containing a point, or the first one
+fn monitor_at(monitors: &[Rect], point: Option<Point>) -> Option<Rect> {
+    point.and_then(|p| monitors.iter().find(|m| m.contains(p))).or(monitors.first()).copied()
+}
+
+/// Move a window of the given size inside the monitor
+fn clamp(position: Point, size: Size, monitor: Rect) -> Point {
+    let max_x = (monitor.x + monitor.width - size.max_width - MARGIN).max(monitor.x);
+    let max_y = (monitor.y + monitor.height - size.height - MARGIN).max(monitor.y);
+    Point { x: position.x.clamp(monitor.x, max_x), y: position.y.clamp(monitor.y, max_y) }
+}
+
+/// Where to open the window, in the provider's units. `size_on` gives the
+/// window size for the monitor it opens on, so it can be fitted to it.
+/// None leaves the placement to the window manager.
+pub fn place(placement: Placement, screen: &dyn ScreenProvider, size_on: impl FnOnce(Rect) -> Size) -> Option<Point> {
+    if placement == Placement::System {
+        return None;
+    }
+    let pointer = screen.pointer();
+    let caret = if placement == Placement::Caret { screen.caret() } else { None };
+    let anchor = caret.map(|c| Point { x: c.x, y: c.y }).or(pointer);
+    let monitor = monitor_at(&screen.monitors(), anchor)?;
+    let size = size_on(monitor);
+
+    // Kept clear of the monitor edges at its widest
+    let centered = clamp(
+        Point { x: monitor.x + (monitor.width - size.width) / 2, y: monitor.y + (monitor.height - size.height) / 2 },
+        size,
+        monitor,
+    );
+    let at_pointer = pointer.map_or(centered, |p| clamp(p, size, monitor));
+    Some(match (placement, caret) {
+        (Placement::Center, _) => centered,
+        // Below the caret, or above it when there is no room below
+        (Placement::Caret, Some(caret)) => {
+            let below = caret.y + caret.height + MARGIN;
+            let fits_below = below + size.height <= monitor.y + monitor.height;
+            let y = if fits_below { below } else { caret.y - size.height - MARGIN };
+            clamp(Point { x: caret.x, y }, size, monitor)
+        }
+        _ => at_pointer,
+    })
+}
@@ -0,0 +1,243 @@
+use std::fmt;
+
+use crate::locale::fold;
+
+/// A parsed search query: every clause must hold for an emoji to match.
+///
+/// Syntax:
+/// - `word` fuzzy prefix match against any keyword
+/// - `=word` exact keyword match
+/// - `"red heart"` exact phrase (consecutive words of the name or keywords)
+/// - `group:animals`, `v:15`, `v:13.1`, `tone:`, `tone:none`, `tone:dark` field filters
+/// - `-term` excludes emoji matching any of the above
+#[derive(Clone, Default)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Clone)]
+pub struct Clause {
+    pub negated: bool,
+    pub term: Term,
+}
+
+/// A single query term; all text is folded (see `locale::fold`)
+#[derive(Clone)]
+pub enum Term {
+    Fuzzy(String),
+    Exact(String),
+    Phrase(Vec<String>),
+    Group(String),
+    Version { major: u32, minor: Option<u32> },
+    Tone(ToneFilter),
+}
+
+#[derive(Clone, Copy)]
+pub enum ToneFilter {
+    /// Emoji that support skin tone modifiers
+    Any,
+    /// Emoji without skin tone support
+    None,
+    Tone(emojis::SkinTone),
+}
+
+/// A query syntax error, with the character column where it occurred
+#[derive(Debug)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (column {})", self.message, self.column + 1)
+    }
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut clauses = Vec::new();
+        let mut pos = 0;
+
+        while pos < chars.len() {
+            if chars[pos].is_whitespace() {
+                pos += 1;
+                continue;
+            }
+
+            let mut negated = false;
+            // `-` only negates when followed by something term-like, so that
+            // emoticons such as `-_-` remain plain search terms
+            if chars[pos] == '-'
+                && chars
+                    .get(pos + 1)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '"' || *c == '=')
+            {
+                negated = true;
+                pos += 1;
+            }
+
+            let term = if chars[pos] == '"' {
+                let close = chars[pos + 1..]
+                    .iter()
+                    .position(|c| *c == '"')
+                    .map(|offset| pos + 1 + offset)
+                    .ok_or_else(|| error(pos, "unterminated quote"))?;
+                let phrase: String = chars[pos + 1..close].iter().collect();
+                let words: Vec<String> = phrase.split_whitespace().map(fold).collect();
+                if words.is_empty() {
+                    return Err(error(pos, "empty phrase"));
+                }
+                pos = close + 1;
+                Term::Phrase(words)
+            } else {
+                let end = chars[pos..]
+                    .iter()
+                    .position(|c| c.is_whitespace())
+                    .map_or(chars.len(), |offset| pos + offset);
+                let token: String = chars[pos..end].iter().collect();
+                let term = parse_token(&token, pos)?;
+                pos = end;
+                term
+            };
+
+            clauses.push(Clause { negated, term });
+        }
+
+        Ok(Self { clauses })
+    }
+
+    /// Folded words from positive text terms, for highlighting matches
+    pub fn highlight_terms(&self) -> Vec<String> {
+        self.clauses
+            .iter()
+            .filter(|c| !c.negated)
+            .flat_map(|c| match &c.term {
+                Term::Fuzzy(t) | Term::Exact(t) => vec![t.clone()],
+                Term::Phrase(words) => words.clone(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+}
+
+fn error(column: usize, message: impl Into<String>) -> ParseError {
+    ParseError { column, message: message.into() }
+}
+
+/// Names accepted before a `:` in field filters
+const FIELDS: [&str; 5] = ["group", "g", "v", "version", "tone"];
+
+/// Parse a whitespace-delimited token starting at `column`
+fn parse_token(token: &str, column: usize) -> Result<Term, ParseError> {
+    if let Some(word) = token.strip_prefix('=') {
+        if word.is_empty() {
+            return Err(error(column, "expected a word after '='"));
+        }
+        return Ok(Term::Exact(fold(word)));
+    }
+
+    // Field filters are `name:value` with an alphabetic name; anything else
+    // containing a colon (e.g. `:D`) is an ordinary term, as is an unknown
+    // name followed by nothing or punctuation (the emoticons `D:` and `O:)`)
+    if let Some((name, value)) = token.split_once(':') {
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()) {
+            let name = name.to_lowercase();
+            if FIELDS.contains(&name.as_str()) || value.starts_with(char::is_alphanumeric) {
+                return parse_field(&name, value, column);
+            }
+        }
+    }
+
+    Ok(Term::Fuzzy(fold(token)))
+}
+
+fn parse_field(name: &str, value: &str, column: usize) -> Result<Term, ParseError> {
+    let value_column = column + name.chars().count() + 1;
+    match name {
+        "group" | "g" => {
+            if value.is_empty() {
+                return Err(error(value_column, "expected a group name after 'group:'"));
+            }
+            Ok(Term::Group(fold(value)))
+        }
+        "v" | "version" => {
+            let (major, minor) = match value.split_once('.') {
+                Some((major, minor)) => (major, Some(minor)),
+                None => (value, None),
+            };
+            let invalid = || error(value_column, format!("invalid Unicode version '{}'", value));
+            let major = major.parse().map_err(|_| invalid())?;
+            let minor = minor.map(|m| m.parse()).transpose().map_err(|_| invalid())?;
+            Ok(Term::Version { major, minor })
+        }
+        "tone" => {
+            let filter = match value.to_lowercase().as_str() {
+                "" | "any" => ToneFilter::Any,
+                "none" => ToneFilter::None,
+                "default" | "yellow" => ToneFilter::Tone(emojis::SkinTone::Default),
+                "light" => ToneFilter::Tone(emojis::SkinTone::Light),
+                "medium-light" => ToneFilter::Tone(emojis::SkinTone::MediumLight),
+                "medium" => ToneFilter::Tone(emojis::SkinTone::Medium),
+                "medium-dark" => ToneFilter::Tone(emojis::SkinTone::MediumDark),
+                "dark" => ToneFilter::Tone(emojis::SkinTone::Dark),
+                _ => return Err(error(value_column, format!("unknown skin tone '{}'", value))),
+            };
+            Ok(Term::Tone(filter))
+        }
+        _ => Err(error(column, format!("unknown field '{}:'", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<Clause> {
+        Query::parse(input).unwrap_or_else(|e| panic!("{}: {}", input, e)).clauses
+    }
+
+    #[test]
+    fn parses_words_phrases_and_exclusions() {
+        let clauses = parse("red -heart \"Big Smile\" =cat");
+        assert!(matches!(&clauses[0], Clause { negated: false, term: Term::Fuzzy(t) } if t == "red"));
+        assert!(matches!(&clauses[1], Clause { negated: true, term: Term::Fuzzy(t) } if t == "heart"));
+        assert!(matches!(&clauses[2].term, Term::Phrase(words) if words == &["big", "smile"]));
+        assert!(matches!(&clauses[3].term, Term::Exact(t) if t == "cat"));
+    }
+
+    #[test]
+    fn folds_diacritics() {
+        assert!(matches!(&parse("Café")[0].term, Term::Fuzzy(t) if t == "cafe"));
+    }
+
+    #[test]
+    fn parses_field_filters() {
+        let clauses = parse("group:animals v:13.1 tone: -tone:dark");
+        assert!(matches!(&clauses[0].term, Term::Group(g) if g == "animals"));
+        assert!(matches!(clauses[1].term, Term::Version { major: 13, minor: Some(1) }));
+        assert!(matches!(clauses[2].term, Term::Tone(ToneFilter::Any)));
+        assert!(clauses[3].negated);
+        assert!(matches!(clauses[3].term, Term::Tone(ToneFilter::Tone(emojis::SkinTone::Dark))));
+    }
+
+    #[test]
+    fn keeps_emoticons_as_terms() {
+        for emoticon in [":d", "d:", "o:)", "o:-)", "-_-", ":-("] {
+            let clauses = parse(emoticon);
+            assert_eq!(clauses.len(), 1, "{}", emoticon);
+            assert!(!clauses[0].negated, "{}", emoticon);
+            assert!(matches!(&clauses[0].term, Term::Fuzzy(t) if t == emoticon), "{}", emoticon);
+        }
+    }
+
+    #[test]
+    fn reports_errors_with_column() {
+        let error = |input: &str| Query::parse(input).err().map(|e| (e.column, e.message));
+        assert_eq!(error("a \"red"), Some((2, "unterminated quote".to_string())));
+        assert_eq!(error("foo:bar"), Some((0, "unknown field 'foo:'".to_string())));
+        assert_eq!(error("v:x"), Some((2, "invalid Unicode version 'x'".to_string())));
+        assert!(error("=").is_some());
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use crate::history::Selection;
+use crate::store::FRECENCY_HALF_LIFE_SECS;
+
+pub const DAY_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// A way of scoring emoji from past selections. Set with `ranking` in the
+/// config, and compared on recorded sessions by `emoru eval`.
+///
+/// Only selections whose query is prefix-related to the current one count
+/// (all of them for an empty query).
+pub trait Ranker {
+    /// Which ranker this is, for stores that score selections themselves
+    fn kind(&self) -> Kind;
+
+    /// The name that selects this ranker (see `parse`)
+    fn name(&self) -> String {
+        match self.kind() {
+            Kind::Exponential { half_life_secs, similarity } => {
+                let base = if similarity { "similarity" } else { "frecency" };
+                if half_life_secs == FRECENCY_HALF_LIFE_SECS {
+                    base.to_string()
+                } else {
+                    format!("{}:{}", base, half_life_secs / DAY_SECS)
+                }
+            }
+            Kind::Buckets => "buckets".to_string(),
+            Kind::Count => "count".to_string(),
+            Kind::Recency => "recency".to_string(),
+            Kind::NoHistory => "none".to_string(),
+        }
+    }
+
+    /// Score of one selection made `age_secs` ago
+    fn weight(&self, selection: &Selection, age_secs: f64) -> f64;
+
+    /// Whether selections count by how close their query is to the current
+    /// one (see `query_similarity`), rather than fully for any prefix-related query
+    fn similarity_weighted(&self) -> bool {
+        false
+    }
+
+    /// How the scores of an emoji's selections add up
+    fn combine(&self, total: f64, score: f64) -> f64 {
+        total + score
+    }
+
+    /// Scores per emoji code for `query` at time `now`. With `app`, only
+    /// selections made in that application count.
+    fn scores(&self, selections: &[Selection], query: &str, app: Option<&str>, now: u64) -> HashMap<String, f64> {
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        let query = query.to_lowercase();
+
+        for sel in selections {
+            if app.is_some_and(|app| sel.app.as_deref() != Some(app)) {
+                continue;
+            }
+            if !query.is_empty() && !queries_match(&query, &sel.query) {
+                continue;
+            }
+            let mut score = self.weight(sel, now.saturating_sub(sel.ts) as f64);
+            if self.similarity_weighted() {
+                score *= query_similarity(&query, &sel.query);
+            }
+            let total = scores.entry(sel.code.clone()).or_insert(0.0);
+            *total = self.combine(*total, score);
+        }
+
+        scores
+    }
+}
+
+/// The built-in rankers and their parameters
+pub enum Kind {
+    Exponential { half_life_secs: f64, similarity: bool },
+    Buckets,
+    Count,
+    Recency,
+    NoHistory,
+}
+
+/// Check if two queries are prefix-related (one is prefix of the other)
+pub fn queries_match(current: &str, stored: &str) -> bool {
+    current.starts_with(stored) || stored.starts_with(current)
+}
+
+/// How alike two prefix-related queries are, from 1 when equal down towards
+/// 0 as one gets longer than the other: `he` is closer to `hea` than to `heart`
+pub fn query_similarity(current: &str, stored: &str) -> f64 {
+    if current.is_empty() {
+        return 1.0;
+    }
+    let (a, b) = (current.chars().count(), stored.chars().count());
+    (1 + a.min(b)) as f64 / (1 + a.max(b)) as f64
+}
+
+/// Selections decay exponentially, halving in weight every half-life
+pub struct Exponential {
+    pub half_life_secs: f64,
+    /// Weight prefix-related queries by their similarity to the current one
+    pub similarity: bool,
+}
+
+impl Ranker for Exponential {
+    fn kind(&self) -> Kind {
+        Kind::Exponential { half_life_secs: self.half_life_secs, similarity: self.similarity }
+    }
+
+    fn weight(&self, selection: &Selection, age_secs: f64) -> f64 {
+        // Compacted aggregates hold a day's selections decayed with the
+        // default half-life, so other half-lives are off by at most a day of decay
+        selection.weight * 0.5_f64.powf(age_secs / self.half_life_secs)
+    }
+
+    fn similarity_weighted(&self) -> bool {
+        self.similarity
+    }
+}
+
+/// Firefox-style frecency: each selection is worth a fixed amount for the
+/// age bucket it falls in
+pub struct Buckets;
+
+/// Upper age bound in days and weight of each bucket; older selections are worth 0.1
+pub const BUCKETS: [(f64, f64); 4] = [(4.0, 1.0), (14.0, 0.7), (31.0, 0.5), (90.0, 0.3)];
+
+impl Ranker for Buckets {
+    fn kind(&self) -> Kind {
+        Kind::Buckets
+    }
+
+    fn weight(&self, selection: &Selection, age_secs: f64) -> f64 {
+        let days = age_secs / DAY_SECS;
+        let bucket = BUCKETS.iter().find(|(max_days, _)| days <= *max_days).map_or(0.1, |(_, w)| *w);
+        f64::from(selection.count) * bucket
+    }
+}
+
+/// How many times each emoji was selected, however long ago
+pub struct Count;
+
+impl Ranker for Count {
+    fn kind(&self) -> Kind {
+        Kind::Count
+    }
+
+    fn weight(&self, selection: &Selection, _age_secs: f64) -> f64 {
+        f64::from(selection.count)
+    }
+}
+
+/// Most recently selected first, however often
+pub struct Recency;
+
+impl Ranker for Recency {
+    fn kind(&self) -> Kind {
+        Kind::Recency
+    }
+
+    fn weight(&self, _selection: &Selection, age_secs: f64) -> f64 {
+        1.0 / (1.0 + age_secs / DAY_SECS)
+    }
+
+    fn combine(&self, total: f64, score: f64) -> f64 {
+        total.max(score)
+    }
+}
+
+/// Index order only, ignoring the history
+pub struct NoHistory;
+
+impl Ranker for NoHistory {
+    fn kind(&self) -> Kind {
+        Kind::NoHistory
+    }
+
+    fn weight(&self, _selection: &Selection, _age_secs: f64) -> f64 {
+        0.0
+    }
+
+    fn scores(&self, _selections: &[Selection], _query: &str, _app: Option<&str>, _now: u64) -> HashMap<String, f64> {
+        HashMap::new()
+    }
+}
+
+/// The picker's default ranker
+pub fn default_ranker() -> Box<dyn Ranker> {
+    Box::new(Exponential { half_life_secs: FRECENCY_HALF_LIFE_SECS, similarity: false })
+}
+
+/// The ranker named in the config, or the default with a warning if the name is invalid
+pub fn configured(spec: &str) -> Box<dyn Ranker> {
+    parse(spec).unwrap_or_else(|e| {
+        eprintln!("emoru: {}, using the default", e);
+        default_ranker()
+    })
+}
+
+/// Parse a ranker name: `frecency` (exponential decay, the default) or
+/// `frecency:DAYS` for another half-life, `similarity[:DAYS]` (the same,
+/// weighting queries by closeness), `buckets`, `count`, `recency` or `none`
+pub fn parse(spec: &str) -> Result<Box<dyn Ranker>, String> {
+    let (name, days) = match spec.split_once(':') {
+        Some((name, days)) => (name, Some(days)),
+        None => (spec, None),
+    };
+    let half_life_secs = match days {
+        None => FRECENCY_HALF_LIFE_SECS,
+        Some(days) => match days.parse::<f64>() {
+            Ok(days) if days > 0.0 => days * DAY_SECS,
+            _ => return Err(format!("invalid half-life '{}', expected a number of days", days)),
+        },
+    };
+
+    match (name, days) {
+        ("frecency", _) => Ok(Box::new(Exponential { half_life_secs, similarity: false })),
+        ("similarity", _) => Ok(Box::new(Exponential { half_life_secs, similarity: true })),
+        ("buckets", None) => Ok(Box::new(Buckets)),
+        ("count" | "frequency", None) => Ok(Box::new(Count)),
+        ("recency", None) => Ok(Box::new(Recency)),
+        ("none", None) => Ok(Box::new(NoHistory)),
+        _ => Err(format!(
+            "unknown ranking '{}', expected frecency[:DAYS], similarity[:DAYS], buckets, count, recency or none",
+            spec
+        )),
+    }
+}
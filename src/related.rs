@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use crate::history::Selection;
+
+/// Two selections at most this far apart count as used together
+pub const CO_SELECTION_WINDOW_SECS: u64 = 5 * 60;
+
+/// How often emoji are selected together, built from the selection history
+pub struct CoOccurrence {
+    /// code -> (related code -> (times selected together, last time together))
+    pairs: HashMap<String, HashMap<String, (u32, u64)>>,
+}
+
+impl CoOccurrence {
+    pub fn build(selections: &[Selection]) -> Self {
+        // Compacted aggregates have no individual timestamps to pair up
+        let mut sorted: Vec<&Selection> = selections.iter().filter(|s| !s.compacted).collect();
+        sorted.sort_by_key(|s| s.ts);
+
+        let mut pairs: HashMap<String, HashMap<String, (u32, u64)>> = HashMap::new();
+        for (i, first) in sorted.iter().enumerate() {
+            for second in sorted[i + 1..]
+                .iter()
+                .take_while(|s| s.ts - first.ts <= CO_SELECTION_WINDOW_SECS)
+            {
+                if first.code == second.code {
+                    continue;
+                }
+                for (a, b) in [(&first.code, &second.code), (&second.code, &first.code)] {
+                    let entry = pairs
+                        .entry(a.clone())
+                        .or_default()
+                        .entry(b.clone())
+                        .or_insert((0, 0));
+                    entry.0 += 1;
+                    entry.1 = entry.1.max(second.ts);
+                }
+            }
+        }
+
+        Self { pairs }
+    }
+
+    /// Emoji most often selected together with `code`, with their counts.
+    /// Ties are broken by the most recent co-selection.
+    pub fn related(&self, code: &str, limit: usize) -> Vec<(String, u32)> {
+        let Some(related) = self.pairs.get(code) else {
+            return Vec::new();
+        };
+
+        let mut ranked: Vec<(&String, &(u32, u64))> = related.iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(a.1));
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(code, (count, _))| (code.clone(), *count))
+            .collect()
+    }
+}
+
+/// The raw selections within `window_secs` of a selection of `code`: all
+/// that `CoOccurrence` needs to find the emoji used together with it
+pub fn selections_around(selections: &[Selection], code: &str, window_secs: u64) -> Vec<Selection> {
+    let times: Vec<u64> = selections.iter().filter(|s| !s.compacted && s.code == code).map(|s| s.ts).collect();
+    selections
+        .iter()
+        .filter(|s| !s.compacted && times.iter().any(|ts| s.ts.abs_diff(*ts) <= window_secs))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(ts: u64, code: &str) -> Selection {
+        let code = code.to_string();
+        Selection { code, query: String::new(), ts, app: None, weight: 1.0, count: 1, compacted: false }
+    }
+
+    #[test]
+    fn orders_related_emoji_by_co_selection() {
+        let selections = vec![
+            select(1_000, "1F382"),
+            select(1_010, "1F389"),
+            select(1_020, "1F381"),
+            select(5_000, "1F382"),
+            select(5_030, "1F389"),
+            select(9_000, "1F382"),
+            select(9_100, "1F388"),
+            // Too late to count with the cake
+            select(9_600, "1F37A"),
+            Selection { compacted: true, count: 9, ..select(9_000, "1F381") },
+        ];
+
+        let co = CoOccurrence::build(&selections);
+        // The balloon ties with the gift but was selected with the cake more recently
+        let related = |limit| co.related("1F382", limit).into_iter().map(|(code, n)| format!("{} {}", code, n));
+        assert_eq!(related(5).collect::<Vec<_>>(), ["1F389 2", "1F388 1", "1F381 1"]);
+        assert_eq!(related(1).collect::<Vec<_>>(), ["1F389 2"]);
+        assert!(co.related("1F600", 5).is_empty());
+
+        let around: Vec<u64> = selections_around(&selections, "1F389", 60).iter().map(|s| s.ts).collect();
+        assert_eq!(around, [1_000, 1_010, 1_020, 5_000, 5_030]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use crate::favorites::Favorites;
+use crate::index::{group_name, EmojiRecord};
+use crate::locale::{fold, fold_char};
+use crate::query::{Query, Term, ToneFilter};
+
+/// Check if a search term matches a word using fuzzy prefix matching:
+/// - First char of term must match first char of word
+/// - Remaining chars must appear in order (subsequence) in the word
+///
+/// Both term and word are expected to be folded (see `locale::fold`),
+/// which makes matching case- and diacritic-insensitive.
+pub fn term_matches_word(term: &str, word: &str) -> bool {
+    let mut term_chars = term.chars();
+    let mut word_chars = word.chars();
+
+    // First char must match word start
+    match (term_chars.next(), word_chars.next()) {
+        (Some(tc), Some(wc)) if tc == wc => {}
+        (None, _) => return true, // empty term matches everything
+        _ => return false,
+    }
+
+    // Remaining chars must appear in order (subsequence)
+    for tc in term_chars {
+        loop {
+            match word_chars.next() {
+                Some(wc) if wc == tc => break,
+                Some(_) => continue,
+                None => return false,
+            }
+        }
+    }
+
+    true
+}
+
+/// Check if an emoji entry satisfies every clause of a query
+pub fn entry_matches_query(entry: &EmojiRecord, query: &Query) -> bool {
+    query
+        .clauses
+        .iter()
+        .all(|clause| term_matches_entry(&clause.term, entry) != clause.negated)
+}
+
+fn term_matches_entry(term: &Term, entry: &EmojiRecord) -> bool {
+    match term {
+        Term::Fuzzy(t) => entry.words.iter().any(|word| term_matches_word(t, word)),
+        Term::Exact(t) => entry.words.iter().any(|word| word == t),
+        Term::Phrase(phrase) => {
+            // Index descriptions are keyword bags, so phrases are mostly
+            // found in the official Unicode name ("red heart")
+            let name_words: Vec<String> = entry
+                .meta
+                .map(|meta| meta.name().split_whitespace().map(fold).collect())
+                .unwrap_or_default();
+            [&entry.words, &name_words]
+                .iter()
+                .any(|words| words.windows(phrase.len()).any(|window| window == phrase.as_slice()))
+        }
+        Term::Group(g) => entry.meta.is_some_and(|meta| {
+            group_name(meta.group())
+                .split_whitespace()
+                .any(|word| term_matches_word(g, word))
+        }),
+        Term::Version { major, minor } => entry.meta.is_some_and(|meta| {
+            let version = meta.unicode_version();
+            version.major() == *major && minor.is_none_or(|m| version.minor() == m)
+        }),
+        Term::Tone(filter) => {
+            let tone = entry.meta.and_then(|meta| meta.skin_tone());
+            match filter {
+                ToneFilter::Any => tone.is_some(),
+                ToneFilter::None => tone.is_none(),
+                ToneFilter::Tone(wanted) => tone == Some(*wanted),
+            }
+        }
+    }
+}
+
+/// Whether the query names an entry exactly: a `=word` or phrase clause
+/// matching it, or a plain term that is a whole keyword
+fn queried_exactly(entry: &EmojiRecord, query: &Query) -> bool {
+    query.clauses.iter().filter(|clause| !clause.negated).any(|clause| match &clause.term {
+        Term::Fuzzy(t) => entry.words.iter().any(|word| word == t),
+        term @ (Term::Exact(_) | Term::Phrase(_)) => term_matches_entry(term, entry),
+        _ => false,
+    })
+}
+
+/// Filter the index by a query and rank by frecency (highest first).
+/// Ties keep index order. Hidden emoji are left out unless queried exactly.
+pub fn rank<'a>(
+    emojis: &'a [EmojiRecord],
+    query: &Query,
+    frecency: &HashMap<String, f64>,
+    favorites: &Favorites,
+) -> Vec<&'a EmojiRecord> {
+    let get_frecency = |entry: &EmojiRecord| -> f64 { *frecency.get(&entry.code).unwrap_or(&0.0) };
+
+    let mut filtered: Vec<&EmojiRecord> = emojis
+        .iter()
+        .filter(|e| entry_matches_query(e, query))
+        .filter(|e| !favorites.is_hidden(&e.code) || queried_exactly(e, query))
+        .collect();
+
+    filtered.sort_by(|a, b| {
+        let fa = get_frecency(a);
+        let fb = get_frecency(b);
+        fb.partial_cmp(&fa).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    filtered
+}
+
+/// The picker's results for a query, best first: ranked matches, or every
+/// emoji by frecency when nothing matches. Pinned emoji lead an empty query,
+/// and an emoji whose emoticon is the whole query comes first.
+pub fn results<'a>(
+    emojis: &'a [EmojiRecord],
+    query: &Query,
+    frecency: &HashMap<String, f64>,
+    favorites: &Favorites,
+    emoticon_match: Option<&'a EmojiRecord>,
+) -> Vec<&'a EmojiRecord> {
+    let mut results = rank(emojis, query, frecency, favorites);
+    if results.is_empty() {
+        // Show top frecency emojis when nothing matches
+        results = rank(emojis, &Query::default(), frecency, favorites);
+    } else if query.clauses.is_empty() {
+        // Stable sort: pins in pin order, then the rest as ranked
+        results.sort_by_key(|m| favorites.pin_position(&m.code).unwrap_or(usize::MAX));
+    }
+
+    // A query that is exactly an emoticon jumps straight to its emoji
+    if let Some(record) = emoticon_match {
+        results.retain(|m| m.code != record.code);
+        results.insert(0, record);
+    }
+    results
+}
+
+/// Find character indices that match a term using fuzzy subsequence matching
+/// Returns None if no match, or Some(indices) of matched characters in word
+///
+/// The term must already be folded; the word is folded one character at a
+/// time so the returned byte indices point into the original word.
+pub fn find_fuzzy_match_indices(term: &str, word: &str) -> Option<Vec<usize>> {
+    let mut term_chars = term.chars().peekable();
+    let mut indices = Vec::new();
+
+    for (idx, wc) in word.char_indices() {
+        let Some(&first_pending) = term_chars.peek() else {
+            break;
+        };
+        let folded: Vec<char> = fold_char(wc).collect();
+
+        // First char must match word start
+        if idx == 0 && folded.first() != Some(&first_pending) {
+            return None;
+        }
+
+        // Remaining chars must appear in order
+        let mut matched = false;
+        for fc in folded {
+            if term_chars.peek() == Some(&fc) {
+                term_chars.next();
+                matched = true;
+            }
+        }
+        if matched {
+            indices.push(idx);
+        }
+    }
+
+    if term_chars.peek().is_some() {
+        None
+    } else {
+        Some(indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> Vec<EmojiRecord> {
+        ["😀| grinning face | 1F600", "😺| grinning cat | 1F63A", "🐈| cat | 1F408", "🎉| party popper | 1F389"]
+            .iter()
+            .filter_map(|line| EmojiRecord::parse(line))
+            .collect()
+    }
+
+    fn codes(results: &[&EmojiRecord]) -> Vec<String> {
+        results.iter().map(|r| r.code.clone()).collect()
+    }
+
+    fn query(input: &str) -> Query {
+        Query::parse(input).unwrap()
+    }
+
+    #[test]
+    fn ranks_by_frecency_and_leads_an_empty_query_with_pins() {
+        let index = index();
+        let frecency = HashMap::from([("1F408".to_string(), 2.0), ("1F63A".to_string(), 1.0)]);
+        let favorites = Favorites { pinned: vec!["1F389".to_string(), "1F600".to_string()], hidden: Vec::new() };
+
+        let empty = results(&index, &query(""), &frecency, &favorites, None);
+        assert_eq!(codes(&empty), ["1F389", "1F600", "1F408", "1F63A"]);
+        // Pins don't reorder a search
+        let searched = results(&index, &query("gr"), &frecency, &favorites, None);
+        assert_eq!(codes(&searched), ["1F63A", "1F600"]);
+    }
+
+    #[test]
+    fn leaves_out_hidden_emoji_unless_queried_exactly() {
+        let index = index();
+        let favorites = Favorites { pinned: Vec::new(), hidden: vec!["1F408".to_string()] };
+        let none = HashMap::new();
+
+        assert_eq!(codes(&rank(&index, &query(""), &none, &favorites)), ["1F600", "1F63A", "1F389"]);
+        assert_eq!(codes(&rank(&index, &query("ca"), &none, &favorites)), ["1F63A"]);
+        // A whole keyword, `=word` or phrase names the hidden emoji
+        assert_eq!(codes(&rank(&index, &query("cat"), &none, &favorites)), ["1F63A", "1F408"]);
+        assert_eq!(codes(&rank(&index, &query("=cat"), &none, &favorites)), ["1F63A", "1F408"]);
+        assert_eq!(codes(&rank(&index, &query("\"cat\""), &none, &favorites)), ["1F63A", "1F408"]);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::history::LogEvent;
+
+/// Keystrokes further apart than this belong to different picker sessions
+pub const SESSION_GAP_SECS: u64 = 2 * 60;
+
+/// One use of the picker: the keys typed, ending in a selection
+pub struct Session {
+    /// Keys in the order they were pressed (`"p"`, `"backspace"`, `"down"`, ...)
+    pub keys: Vec<String>,
+    /// Time of the first key, or of the selection when no keys were recorded
+    pub start: u64,
+    /// Time of the selection
+    pub ts: u64,
+    pub code: String,
+    /// The query as typed
+    pub query: String,
+}
+
+impl Session {
+    /// Whether the keys of this session were recorded. A selection with a
+    /// query but no keystrokes was made with keystroke logging off.
+    pub fn has_keystrokes(&self) -> bool {
+        !self.keys.is_empty() || self.query.is_empty()
+    }
+}
+
+/// Split a time-ordered event log into picker sessions. Keystrokes after the
+/// previous selection, with no long pause between them, lead to the next
+/// selection. Compacted aggregates have no sessions and are skipped.
+pub fn sessions(events: &[LogEvent]) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    let mut keys: Vec<String> = Vec::new();
+    let mut start = 0;
+    let mut last_key = 0;
+
+    for event in events {
+        match event {
+            LogEvent::Keystroke { ts, key } => {
+                if keys.is_empty() || ts.saturating_sub(last_key) > SESSION_GAP_SECS {
+                    keys.clear();
+                    start = *ts;
+                }
+                keys.push(key.clone());
+                last_key = *ts;
+            }
+            LogEvent::Select { ts, code, query, .. } => {
+                if ts.saturating_sub(last_key) > SESSION_GAP_SECS {
+                    keys.clear();
+                }
+                sessions.push(Session {
+                    start: if keys.is_empty() { *ts } else { start },
+                    keys: std::mem::take(&mut keys),
+                    ts: *ts,
+                    code: code.clone(),
+                    query: query.clone(),
+                });
+            }
+            LogEvent::Aggregate { .. } => {}
+        }
+    }
+
+    sessions
+}
@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use crate::history::{
+    self, create_private_dir, open_private, ClearFilter, CompactStats, LogEvent, QueryCodeCount, Selection,
+    Skipped, COMPACTION_DAY_SECS, COMPACTION_SLACK_SECS,
+};
+use crate::ranker::{query_similarity, Kind, Ranker, BUCKETS, DAY_SECS};
+use crate::store::{HistoryStore, FRECENCY_HALF_LIFE_SECS};
+
+/// Version of the database layout, kept in `PRAGMA user_version`.
+/// Version 2 added `selections.app`.
+const DB_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS selections (
+        ts INTEGER NOT NULL,
+        code TEXT NOT NULL,
+        query TEXT NOT NULL,
+        weight REAL NOT NULL DEFAULT 1.0,
+        count INTEGER NOT NULL DEFAULT 1,
+        compacted INTEGER NOT NULL DEFAULT 0,
+        app TEXT
+    );
+    CREATE INDEX IF NOT EXISTS selections_by_query ON selections (query);
+    CREATE INDEX IF NOT EXISTS selections_by_ts ON selections (ts);
+    CREATE INDEX IF NOT EXISTS selections_by_code ON selections (code);
+    CREATE TABLE IF NOT EXISTS keystrokes (
+        ts INTEGER NOT NULL,
+        key TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS keystrokes_by_ts ON keystrokes (ts);
+";
+
+/// History in an SQLite database. The selections matching a query are found
+/// with the query index and scored by the ranker.
+pub struct SqliteStore {
+    conn: Connection,
+    path: PathBuf,
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+const SELECT_SELECTIONS: &str = "SELECT ts, code, query, weight, count, compacted, app FROM selections";
+
+fn selection_from_row(row: &rusqlite::Row) -> rusqlite::Result<Selection> {
+    Ok(Selection {
+        ts: row.get(0)?,
+        code: row.get(1)?,
+        query: row.get(2)?,
+        weight: row.get(3)?,
+        count: row.get(4)?,
+        compacted: row.get(5)?,
+        app: row.get(6)?,
+    })
+}
+
+/// A ranker's `weight` and `combine` as SQL: an expression over the columns
+/// `weight`, `count`, `age` (seconds) and `similarity`, and the aggregate
+/// function adding up an emoji's scores. `None` when nothing scores.
+fn ranker_sql(kind: Kind) -> Option<(String, &'static str)> {
+    match kind {
+        Kind::Exponential { half_life_secs, similarity } => {
+            let score = format!("weight * decay(age, {:?})", half_life_secs);
+            Some((if similarity { score + " * similarity" } else { score }, "SUM"))
+        }
+        Kind::Buckets => {
+            let cases: String = BUCKETS
+                .iter()
+                .map(|(max_days, weight)| format!("WHEN age <= {:?} THEN {:?} ", max_days * DAY_SECS, weight))
+                .collect();
+            Some((format!("count * CASE {}ELSE 0.1 END", cases), "SUM"))
+        }
+        Kind::Count => Some(("count".to_string(), "SUM")),
+        Kind::Recency => Some((format!("1.0 / (1.0 + age / {:?})", DAY_SECS), "MAX")),
+        Kind::NoHistory => None,
+    }
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        // Create the file first so it is readable by the owner only
+        open_private(OpenOptions::new().create(true).append(true), path)?;
+
+        let conn = Connection::open(path).map_err(to_io)?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(to_io)?;
+
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(to_io)?;
+        if version > DB_VERSION {
+            return Err(io::Error::other(format!(
+                "{} was written by a newer version of emoru (schema {})",
+                path.display(),
+                version
+            )));
+        }
+        conn.execute_batch(SCHEMA).map_err(to_io)?;
+        if version == 1 {
+            conn.execute_batch("ALTER TABLE selections ADD COLUMN app TEXT").map_err(to_io)?;
+        }
+        conn.pragma_update(None, "user_version", DB_VERSION).map_err(to_io)?;
+
+        // decay(age_secs, half_life_secs): the weight left after `age_secs`
+        conn.create_scalar_function(
+            "decay",
+            2,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| {
+                let (age, half_life): (f64, f64) = (ctx.get(0)?, ctx.get(1)?);
+                Ok(0.5_f64.powf(age / half_life))
+            },
+        )
+        .map_err(to_io)?;
+        conn.create_scalar_function(
+            "query_similarity",
+            2,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |ctx| Ok(query_similarity(&ctx.get::<String>(0)?, &ctx.get::<String>(1)?)),
+        )
+        .map_err(to_io)?;
+
+        Ok(Self { conn, path: path.to_path_buf() })
+    }
+
+    /// Copy the events of a JSONL history into the database, skipping those
+    /// already present, so importing twice is harmless. Returns the number of
+    /// events added.
+    pub fn import_jsonl(&self, path: &Path) -> io::Result<(usize, Skipped)> {
+        let mut events = Vec::new();
+        let skipped = history::read_events(path, |event| events.push(event))?;
+        Ok((self.import(events)?, skipped))
+    }
+
+    fn insert(conn: &Connection, event: &LogEvent) -> rusqlite::Result<usize> {
+        match event {
+            LogEvent::Keystroke { ts, key } => {
+                conn.execute("INSERT INTO keystrokes (ts, key) VALUES (?1, ?2)", params![ts, key])
+            }
+            LogEvent::Select { ts, code, query, app } => conn.execute(
+                "INSERT INTO selections (ts, code, query, app) VALUES (?1, ?2, ?3, ?4)",
+                params![ts, code, query.to_lowercase(), app],
+            ),
+            LogEvent::Aggregate { ts, code, query, app, weight, count } => conn.execute(
+                "INSERT INTO selections (ts, code, query, app, weight, count, compacted)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)",
+                params![ts, code, query.to_lowercase(), app, weight, count],
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+impl SqliteStore {
+    /// Every selection, including compacted aggregates
+    fn selections(&self) -> io::Result<Vec<Selection>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} ORDER BY ts", SELECT_SELECTIONS))
+            .map_err(to_io)?;
+        let rows = stmt.query_map([], selection_from_row).map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+}
+
+impl HistoryStore for SqliteStore {
+    fn append(&self, event: &LogEvent) -> io::Result<()> {
+        Self::insert(&self.conn, event).map(|_| ()).map_err(to_io)
+    }
+
+    fn query_counts(&self) -> io::Result<Vec<QueryCodeCount>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT query, code, SUM(count) FROM selections WHERE query != '' GROUP BY query, code")
+            .map_err(to_io)?;
+        let rows = stmt
+            .query_map([], |row| Ok(QueryCodeCount { query: row.get(0)?, code: row.get(1)?, count: row.get(2)? }))
+            .map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    fn latest_selection(&self, now: u64) -> io::Result<Option<Selection>> {
+        self.conn
+            .query_row(
+                &format!("{} WHERE compacted = 0 AND ts <= ?1 ORDER BY ts DESC LIMIT 1", SELECT_SELECTIONS),
+                params![now as i64],
+                selection_from_row,
+            )
+            .optional()
+            .map_err(to_io)
+    }
+
+    fn selections_around(&self, code: &str, window_secs: u64) -> io::Result<Vec<Selection>> {
+        // The selections of `code` by the code index, then their neighbours by the time index
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT DISTINCT s.ts, s.code, s.query, s.weight, s.count, s.compacted, s.app, s.rowid
+                 FROM selections a JOIN selections s ON s.ts BETWEEN a.ts - ?2 AND a.ts + ?2
+                 WHERE a.code = ?1 AND a.compacted = 0 AND s.compacted = 0
+                 ORDER BY s.ts",
+            )
+            .map_err(to_io)?;
+        let rows = stmt.query_map(params![code, window_secs as i64], selection_from_row).map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    fn events(&self) -> io::Result<Vec<LogEvent>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                // Keystrokes sort before a selection made in the same second
+                "SELECT ts, code, query, weight, count, compacted, app, NULL, 1 FROM selections
+                 UNION ALL SELECT ts, NULL, NULL, NULL, NULL, NULL, NULL, key, 0 FROM keystrokes
+                 ORDER BY 1, 9",
+            )
+            .map_err(to_io)?;
+        let rows = stmt
+            .query_map([], |row| {
+                let ts = row.get(0)?;
+                Ok(match row.get::<_, Option<String>>(7)? {
+                    Some(key) => LogEvent::Keystroke { ts, key },
+                    None if row.get(5)? => LogEvent::Aggregate {
+                        ts,
+                        code: row.get(1)?,
+                        query: row.get(2)?,
+                        app: row.get(6)?,
+                        weight: row.get(3)?,
+                        count: row.get(4)?,
+                    },
+                    None => LogEvent::Select {
+                        ts,
+                        code: row.get(1)?,
+                        query: row.get(2)?,
+                        app: row.get(6)?,
+                    },
+                })
+            })
+            .map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    fn frecency(
+        &self,
+        ranker: &dyn Ranker,
+        query: &str,
+        app: Option<&str>,
+        now: u64,
+    ) -> io::Result<HashMap<String, f64>> {
+        let query = query.to_lowercase();
+        let Some((score, combine)) = ranker_sql(ranker.kind()) else {
+            return Ok(HashMap::new());
+        };
+
+        // Prefix-related stored queries: those starting with `query` (an index
+        // range scan), and the prefixes of `query` itself (index lookups)
+        let prefixes: Vec<String> = query
+            .char_indices()
+            .map(|(i, _)| &query[..i])
+            .chain([query.as_str()])
+            .map(String::from)
+            .collect();
+        let placeholders = vec!["?"; prefixes.len()].join(", ");
+        let filter = if query.is_empty() {
+            "?1 IS NULL OR app = ?1".to_string()
+        } else {
+            format!("(?1 IS NULL OR app = ?1) AND ((query >= ?4 AND query < ?5) OR query IN ({}))", placeholders)
+        };
+        // Scored per emoji in the database, so only one row per emoji comes back
+        let sql = format!(
+            "SELECT code, {}({}) FROM (
+                 SELECT code, weight, count, MAX(?2 - ts, 0) AS age, query_similarity(?3, query) AS similarity
+                 FROM selections WHERE {})
+             GROUP BY code",
+            combine, score, filter
+        );
+
+        let mut params: Vec<rusqlite::types::Value> =
+            vec![app.map(String::from).into(), (now as i64).into(), query.clone().into()];
+        if !query.is_empty() {
+            params.push(query.clone().into());
+            params.push(format!("{}{}", query, char::MAX).into());
+            params.extend(prefixes.into_iter().map(Into::into));
+        }
+
+        let mut stmt = self.conn.prepare(&sql).map_err(to_io)?;
+        let rows = stmt
+            .query_map(params_from_iter(params), |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    fn import(&self, events: Vec<LogEvent>) -> io::Result<usize> {
+        let events = history::new_events(&self.events()?, events);
+        let tx = self.conn.unchecked_transaction().map_err(to_io)?;
+        for event in &events {
+            Self::insert(&tx, event).map_err(to_io)?;
+        }
+        tx.commit().map_err(to_io)?;
+        Ok(events.len())
+    }
+
+    fn clear(&self, filter: &ClearFilter) -> io::Result<usize> {
+        let before = filter.before.map(|b| b as i64).unwrap_or(i64::MAX);
+        let tx = self.conn.unchecked_transaction().map_err(to_io)?;
+
+        let mut removed = match &filter.code {
+            Some(code) => tx.execute("DELETE FROM selections WHERE ts < ?1 AND code = ?2", params![before, code]),
+            None => tx.execute("DELETE FROM selections WHERE ts < ?1", params![before]),
+        }
+        .map_err(to_io)?;
+        // Keystrokes are not tied to an emoji, so an emoji filter keeps them
+        if filter.code.is_none() {
+            removed += tx
+                .execute("DELETE FROM keystrokes WHERE ts < ?1", params![before])
+                .map_err(to_io)?;
+        }
+
+        tx.commit().map_err(to_io)?;
+        Ok(removed)
+    }
+
+    fn compact(&self, retention_secs: u64, now: u64) -> io::Result<CompactStats> {
+        let cutoff = now.saturating_sub(retention_secs) as i64;
+        let tx = self.conn.unchecked_transaction().map_err(to_io)?;
+
+        let folded_selections: i64 = tx
+            .query_row(
+                "SELECT COUNT(*) FROM selections WHERE ts < ?1 AND compacted = 0",
+                params![cutoff],
+                |row| row.get(0),
+            )
+            .map_err(to_io)?;
+        // Rows up to here are replaced by the aggregates inserted after them
+        let last_row: i64 = tx
+            .query_row("SELECT COALESCE(MAX(rowid), 0) FROM selections", [], |row| row.get(0))
+            .map_err(to_io)?;
+        let aggregates = tx
+            .execute(
+                "INSERT INTO selections (ts, code, query, app, weight, count, compacted)
+                 SELECT last, code, query, app, SUM(weight * decay(last - ts, ?3)), SUM(count), 1
+                 FROM (SELECT *, MAX(ts) OVER (PARTITION BY code, query, app, ts / ?2) AS last
+                       FROM selections WHERE ts < ?1)
+                 GROUP BY code, query, app, ts / ?2",
+                params![cutoff, COMPACTION_DAY_SECS as i64, FRECENCY_HALF_LIFE_SECS],
+            )
+            .map_err(to_io)?;
+        tx.execute("DELETE FROM selections WHERE ts < ?1 AND rowid <= ?2", params![cutoff, last_row])
+            .map_err(to_io)?;
+        let dropped_keystrokes = tx
+            .execute("DELETE FROM keystrokes WHERE ts < ?1", params![cutoff])
+            .map_err(to_io)?;
+
+        tx.commit().map_err(to_io)?;
+        Ok(CompactStats { folded_selections: folded_selections as usize, dropped_keystrokes, aggregates })
+    }
+
+    fn needs_compaction(&self, retention_secs: u64, now: u64) -> bool {
+        let threshold = now.saturating_sub(retention_secs + COMPACTION_SLACK_SECS) as i64;
+        self.conn
+            .query_row(
+                "SELECT 1 WHERE EXISTS (SELECT 1 FROM selections WHERE ts < ?1 AND compacted = 0)
+                    OR EXISTS (SELECT 1 FROM keystrokes WHERE ts < ?1)",
+                params![threshold],
+                |_| Ok(()),
+            )
+            .optional()
+            .is_ok_and(|found| found.is_some())
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::history::tests::{assert_same_scores, sample_events, temp_history, DAY, NOW};
+    use crate::store::JsonlStore;
+
+    #[test]
+    fn compaction_keeps_scores() {
+        let path = temp_history("sqlite-compaction").with_extension("sqlite3");
+        let store = SqliteStore::open(&path).unwrap();
+        store.import(sample_events()).unwrap();
+
+        for (retention_days, now) in [(30, NOW), (7, NOW + 5 * DAY)] {
+            let before = store.selections().unwrap();
+            let stats = store.compact(retention_days * DAY, now).unwrap();
+            assert!(stats.folded_selections > 0);
+            assert_same_scores(&before, &store.selections().unwrap(), now);
+        }
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn frecency_matches_the_rankers() {
+        let path = temp_history("sqlite-frecency");
+        let jsonl = JsonlStore::new(path.clone(), Vec::new());
+        jsonl.import(sample_events()).unwrap();
+        let sqlite = SqliteStore::open(&path.with_extension("sqlite3")).unwrap();
+        sqlite.import(sample_events()).unwrap();
+        sqlite.compact(30 * DAY, NOW).unwrap();
+
+        for spec in ["frecency", "frecency:3", "similarity", "buckets", "count", "recency", "none"] {
+            let ranker = crate::ranker::parse(spec).unwrap();
+            for query in ["", "s", "SMILE", "sm", "heart", "x"] {
+                for app in [None, Some("term")] {
+                    let expected = jsonl.frecency(ranker.as_ref(), query, app, NOW).unwrap();
+                    let actual = sqlite.frecency(ranker.as_ref(), query, app, NOW).unwrap();
+                    // Compaction shifts scores by at most a day's decay
+                    let close = expected.len() == actual.len()
+                        && expected.iter().all(|(code, a)| actual.get(code).is_some_and(|b| (a - b).abs() <= a * 0.03));
+                    assert!(close, "{} {:?}: {:?} != {:?}", spec, query, expected, actual);
+                }
+            }
+        }
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn queries_match_the_jsonl_store() {
+        let path = temp_history("sqlite-queries");
+        let jsonl = JsonlStore::new(path.clone(), Vec::new());
+        jsonl.import(sample_events()).unwrap();
+        let sqlite = SqliteStore::open(&path.with_extension("sqlite3")).unwrap();
+        sqlite.import(sample_events()).unwrap();
+
+        let counts = |store: &dyn HistoryStore| -> Vec<(String, String, u32)> {
+            let mut counts: Vec<_> =
+                store.query_counts().unwrap().into_iter().map(|c| (c.query, c.code, c.count)).collect();
+            counts.sort();
+            counts
+        };
+        assert_eq!(counts(&jsonl), counts(&sqlite));
+
+        let latest = |store: &dyn HistoryStore| store.latest_selection(NOW - 2 * DAY).unwrap().map(|s| (s.ts, s.code));
+        assert_eq!(latest(&jsonl), latest(&sqlite));
+        assert!(latest(&sqlite).is_some());
+
+        let around = |store: &dyn HistoryStore| -> Vec<(u64, String)> {
+            store.selections_around("2764", 60).unwrap().into_iter().map(|s| (s.ts, s.code)).collect()
+        };
+        // Only the smiley picked a minute before each heart
+        assert_eq!(around(&jsonl), around(&sqlite));
+        assert_eq!(around(&sqlite).len(), 2 * 27);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
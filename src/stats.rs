@@ -0,0 +1,260 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::history::{civil_from_days, format_date, LogEvent};
+use crate::index::{find_code, EmojiRecord};
+use crate::locale::normalize_code;
+use crate::session::sessions;
+
+/// Length of the periods in a usage report
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Period {
+    Week,
+    Month,
+    Year,
+}
+
+impl Period {
+    /// Label of the period containing `ts`: the date of the week's Monday, `YYYY-MM` or `YYYY`
+    fn label(self, ts: u64) -> String {
+        let days = (ts / 86_400) as i64;
+        match self {
+            // 1970-01-01 was a Thursday
+            Period::Week => format_date((days - (days + 3).rem_euclid(7)) as u64 * 86_400),
+            Period::Month => {
+                let (year, month, _) = civil_from_days(days);
+                format!("{:04}-{:02}", year, month)
+            }
+            Period::Year => civil_from_days(days).0.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct QueryCount {
+    pub query: String,
+    pub count: u64,
+}
+
+#[derive(Serialize)]
+pub struct EmojiUsage {
+    pub emoji: String,
+    pub code: String,
+    pub count: u64,
+    /// Most common queries that found this emoji
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<QueryCount>,
+}
+
+#[derive(Serialize)]
+pub struct PeriodUsage {
+    pub period: String,
+    pub selections: u64,
+    pub top: Vec<EmojiUsage>,
+}
+
+/// Usage statistics derived from the history
+#[derive(Serialize)]
+pub struct Report {
+    pub selections: u64,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub top: Vec<EmojiUsage>,
+    /// Most recent period first. Compacted history counts on the day it was aggregated to.
+    pub periods: Vec<PeriodUsage>,
+    /// Average keys pressed per selection, over sessions whose keystrokes were recorded
+    pub average_keystrokes: Option<f64>,
+    pub sessions_with_keystrokes: usize,
+    /// Emoji in the index that were never selected
+    pub never_used: Vec<String>,
+    /// How many emoji the text report lists in each section
+    #[serde(skip)]
+    pub limit: usize,
+}
+
+const QUERIES_PER_EMOJI: usize = 3;
+
+/// Build a usage report from a time-ordered event log
+pub fn report(events: &[LogEvent], index: &[EmojiRecord], limit: usize, period: Period) -> Report {
+    let mut totals: HashMap<&str, u64> = HashMap::new();
+    let mut queries: HashMap<&str, HashMap<&str, u64>> = HashMap::new();
+    let mut by_period: BTreeMap<String, HashMap<&str, u64>> = BTreeMap::new();
+    let mut selections = 0;
+    let mut first = None;
+    let mut last = None;
+
+    for event in events {
+        let (ts, code, query, count) = match event {
+            LogEvent::Select { ts, code, query, .. } => (*ts, code, query, 1),
+            LogEvent::Aggregate { ts, code, query, count, .. } => (*ts, code, query, u64::from(*count)),
+            LogEvent::Keystroke { .. } => continue,
+        };
+        // Aggregates are dated by day, so the log is not strictly ordered by them
+        *by_period.entry(period.label(ts)).or_default().entry(code).or_insert(0) += count;
+        first = Some(first.map_or(ts, |first: u64| first.min(ts)));
+        last = Some(last.map_or(ts, |last: u64| last.max(ts)));
+        selections += count;
+        *totals.entry(code).or_insert(0) += count;
+        if !query.is_empty() {
+            *queries.entry(code).or_default().entry(query).or_insert(0) += count;
+        }
+    }
+
+    let usage = |counts: &HashMap<&str, u64>, with_queries: bool| -> Vec<EmojiUsage> {
+        ranked(counts)
+            .into_iter()
+            .take(limit)
+            .map(|(code, count)| EmojiUsage {
+                emoji: find_code(index, code).map_or_else(|| "?".to_string(), |r| r.emoji.clone()),
+                code: code.to_string(),
+                count,
+                queries: match queries.get(code) {
+                    Some(counts) if with_queries => ranked(counts)
+                        .into_iter()
+                        .take(QUERIES_PER_EMOJI)
+                        .map(|(query, count)| QueryCount { query: query.to_string(), count })
+                        .collect(),
+                    _ => Vec::new(),
+                },
+            })
+            .collect()
+    };
+
+    let keystrokes: Vec<usize> = sessions(events)
+        .iter()
+        .filter(|s| s.has_keystrokes())
+        .map(|s| s.keys.len())
+        .collect();
+    let used: HashSet<String> = totals.keys().map(|code| normalize_code(code)).collect();
+
+    Report {
+        selections,
+        first: first.map(format_date),
+        last: last.map(format_date),
+        top: usage(&totals, true),
+        periods: by_period
+            .iter()
+            .rev()
+            .map(|(label, counts)| PeriodUsage {
+                period: label.clone(),
+                selections: counts.values().sum(),
+                top: usage(counts, false),
+            })
+            .collect(),
+        average_keystrokes: (!keystrokes.is_empty())
+            .then(|| keystrokes.iter().sum::<usize>() as f64 / keystrokes.len() as f64),
+        sessions_with_keystrokes: keystrokes.len(),
+        never_used: index
+            .iter()
+            .filter(|r| !used.contains(&normalize_code(&r.code)))
+            .map(|r| r.emoji.clone())
+            .collect(),
+        limit,
+    }
+}
+
+/// Counts sorted highest first, ties by key
+fn ranked<'a>(counts: &HashMap<&'a str, u64>) -> Vec<(&'a str, u64)> {
+    let mut ranked: Vec<(&str, u64)> = counts.iter().map(|(k, v)| (*k, *v)).collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    ranked
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.first, &self.last) {
+            (Some(first), Some(last)) => writeln!(f, "{} selections, {} to {}", self.selections, first, last)?,
+            _ => writeln!(f, "{} selections", self.selections)?,
+        }
+        match self.average_keystrokes {
+            Some(average) => writeln!(
+                f,
+                "{:.1} keystrokes per selection (over {} sessions)",
+                average, self.sessions_with_keystrokes
+            )?,
+            None => writeln!(f, "No keystrokes recorded")?,
+        }
+
+        writeln!(f, "\nTop emoji:")?;
+        for usage in &self.top {
+            let queries: Vec<String> = usage
+                .queries
+                .iter()
+                .map(|q| format!("\"{}\" {}", q.query, q.count))
+                .collect();
+            writeln!(f, "  {}\t{}\t{}", usage.emoji, usage.count, queries.join(", "))?;
+        }
+
+        for period in &self.periods {
+            let top: Vec<String> = period.top.iter().map(|u| format!("{} {}", u.emoji, u.count)).collect();
+            writeln!(f, "\n{} ({} selections): {}", period.period, period.selections, top.join("  "))?;
+        }
+
+        let shown: Vec<&str> = self.never_used.iter().take(self.limit).map(String::as_str).collect();
+        write!(f, "\n{} emoji never used: {}", self.never_used.len(), shown.join(" "))?;
+        if self.never_used.len() > shown.len() {
+            write!(f, " …")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400;
+
+    fn select(ts: u64, code: &str, query: &str) -> LogEvent {
+        LogEvent::Select { ts, code: code.to_string(), query: query.to_string(), app: None }
+    }
+
+    #[test]
+    fn labels_periods() {
+        // 2024-02-29 was a Thursday
+        let leap_day = 19_782 * DAY + 3_600;
+        assert_eq!(Period::Week.label(leap_day), "2024-02-26");
+        assert_eq!(Period::Month.label(leap_day), "2024-02");
+        assert_eq!(Period::Year.label(leap_day), "2024");
+        // Weeks run Monday to Sunday
+        assert_eq!(Period::Week.label(leap_day + 3 * DAY), "2024-02-26");
+        assert_eq!(Period::Week.label(leap_day + 4 * DAY), "2024-03-04");
+    }
+
+    #[test]
+    fn reports_usage_per_period_and_never_used() {
+        let lines = ["😀| grinning | 1F600", "❤️| heart | 2764_FE0F", "👍| thumbs up | 1F44D", "🎉| party | 1F389"];
+        let index: Vec<EmojiRecord> = lines
+            .iter()
+            .filter_map(|line| EmojiRecord::parse(line))
+            .collect();
+        let jan = 19_723 * DAY; // 2024-01-01
+        let events = vec![
+            select(jan, "1F600", "grin"),
+            select(jan + DAY, "1F600", "grin"),
+            select(jan + 40 * DAY, "2764", "heart"),
+            LogEvent::Aggregate {
+                ts: jan,
+                code: "1F600".to_string(),
+                query: "smile".to_string(),
+                app: None,
+                weight: 1.0,
+                count: 5,
+            },
+        ];
+
+        let report = report(&events, &index, 1, Period::Month);
+        assert_eq!(report.selections, 8);
+        assert_eq!(report.top.len(), 1);
+        assert_eq!(report.top[0].count, 7);
+        assert_eq!(report.top[0].queries[0].query, "smile");
+        let periods: Vec<(&str, u64)> = report.periods.iter().map(|p| (p.period.as_str(), p.selections)).collect();
+        assert_eq!(periods, [("2024-02", 1), ("2024-01", 7)]);
+        // A selection without the variation selector still counts the emoji as used
+        assert_eq!(report.never_used, ["👍", "🎉"]);
+        assert!(report.to_string().ends_with("2 emoji never used: 👍 …"));
+    }
+}
@@ -0,0 +1,225 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::history::{
+    self, current_timestamp, get_log_path, ClearFilter, CompactStats, LogEvent, QueryCodeCount, Selection,
+};
+use crate::ranker::Ranker;
+use crate::related;
+
+/// A selection counts half as much after this long, with the default
+/// ranker. Compacted aggregates are weighted with it.
+pub const FRECENCY_HALF_LIFE_SECS: f64 = 7.0 * 24.0 * 60.0 * 60.0; // 7 days
+
+/// Where the history is kept
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// Append-only `history.jsonl`
+    #[default]
+    Jsonl,
+    /// `history.sqlite3` next to it (requires the `sqlite` feature)
+    Sqlite,
+}
+
+/// Persistent history of picker events
+pub trait HistoryStore {
+    /// Append one event
+    fn append(&self, event: &LogEvent) -> io::Result<()>;
+
+    /// Selections per (query, emoji) pair, for learning aliases
+    fn query_counts(&self) -> io::Result<Vec<QueryCodeCount>>;
+
+    /// The latest selection made by `now`, leaving out compacted aggregates
+    fn latest_selection(&self, now: u64) -> io::Result<Option<Selection>>;
+
+    /// The raw selections within `window_secs` of a selection of `code`
+    fn selections_around(&self, code: &str, window_secs: u64) -> io::Result<Vec<Selection>>;
+
+    /// Every event, oldest first
+    fn events(&self) -> io::Result<Vec<LogEvent>>;
+
+    /// Scores per emoji code from `ranker` as of `now`, counting only
+    /// selections whose query is prefix-related to `query` (all of them
+    /// when it is empty), and only those made in `app` when given
+    fn frecency(
+        &self,
+        ranker: &dyn Ranker,
+        query: &str,
+        app: Option<&str>,
+        now: u64,
+    ) -> io::Result<HashMap<String, f64>>;
+
+    /// Add events from another history, skipping those already present.
+    /// Returns the number of events added.
+    fn import(&self, events: Vec<LogEvent>) -> io::Result<usize>;
+
+    /// Remove matching events, returning how many were removed
+    fn clear(&self, filter: &ClearFilter) -> io::Result<usize>;
+
+    /// Fold selections older than the retention window into aggregates and drop old keystrokes
+    fn compact(&self, retention_secs: u64, now: u64) -> io::Result<CompactStats>;
+
+    /// Whether there are raw events well past the retention window
+    fn needs_compaction(&self, retention_secs: u64, now: u64) -> bool;
+
+    /// Where the history is stored, for messages
+    fn location(&self) -> String;
+}
+
+/// Mix application-specific scores into global ones: `app_weight` 0 keeps
+/// the global ranking, 1 ranks by the application's selections alone
+pub fn blend_frecency(
+    global: &HashMap<String, f64>,
+    app: &HashMap<String, f64>,
+    app_weight: f64,
+) -> HashMap<String, f64> {
+    let app_weight = app_weight.clamp(0.0, 1.0);
+    let mut scores: HashMap<String, f64> = global
+        .iter()
+        .map(|(code, score)| (code.clone(), score * (1.0 - app_weight)))
+        .collect();
+    for (code, score) in app {
+        *scores.entry(code.clone()).or_insert(0.0) += score * app_weight;
+    }
+    scores
+}
+
+/// The JSONL history file, plus read-only files from other machines in a
+/// shared history directory. Selections are read once and kept in memory.
+pub struct JsonlStore {
+    path: PathBuf,
+    peers: Vec<PathBuf>,
+    selections: OnceCell<Vec<Selection>>,
+}
+
+impl JsonlStore {
+    pub fn new(path: PathBuf, peers: Vec<PathBuf>) -> Self {
+        Self { path, peers, selections: OnceCell::new() }
+    }
+
+    /// Selections as loaded on first use, warning on stderr about skipped lines
+    fn cached(&self) -> &[Selection] {
+        self.selections.get_or_init(|| {
+            let mut all = Vec::new();
+            for path in std::iter::once(&self.path).chain(&self.peers) {
+                match history::read_selections(path) {
+                    Ok((selections, skipped)) => {
+                        if skipped.total() > 0 {
+                            eprintln!("emoru: skipped {} in {}", skipped, path.display());
+                        }
+                        all.extend(selections);
+                    }
+                    Err(e) => eprintln!("emoru: could not read {}: {}", path.display(), e),
+                }
+            }
+            all.sort_by_key(|s| s.ts);
+            all
+        })
+    }
+
+    fn peer_events(&self) -> io::Result<Vec<LogEvent>> {
+        let mut events = Vec::new();
+        for path in &self.peers {
+            history::read_events(path, |event| events.push(event))?;
+        }
+        Ok(events)
+    }
+}
+
+impl HistoryStore for JsonlStore {
+    fn append(&self, event: &LogEvent) -> io::Result<()> {
+        history::append_event(&self.path, event)
+    }
+
+    fn query_counts(&self) -> io::Result<Vec<QueryCodeCount>> {
+        Ok(history::query_counts(self.cached()))
+    }
+
+    fn latest_selection(&self, now: u64) -> io::Result<Option<Selection>> {
+        Ok(self.cached().iter().filter(|s| !s.compacted && s.ts <= now).max_by_key(|s| s.ts).cloned())
+    }
+
+    fn selections_around(&self, code: &str, window_secs: u64) -> io::Result<Vec<Selection>> {
+        Ok(related::selections_around(self.cached(), code, window_secs))
+    }
+
+    fn events(&self) -> io::Result<Vec<LogEvent>> {
+        let mut events = self.peer_events()?;
+        history::read_events(&self.path, |event| events.push(event))?;
+        events.sort_by_key(LogEvent::ts);
+        Ok(events)
+    }
+
+    fn frecency(
+        &self,
+        ranker: &dyn Ranker,
+        query: &str,
+        app: Option<&str>,
+        now: u64,
+    ) -> io::Result<HashMap<String, f64>> {
+        Ok(ranker.scores(self.cached(), query, app, now))
+    }
+
+    fn import(&self, events: Vec<LogEvent>) -> io::Result<usize> {
+        // Events another machine already shares need no copy in this machine's file
+        let events = history::new_events(&self.peer_events()?, events);
+        history::merge_events(&self.path, events)
+    }
+
+    fn clear(&self, filter: &ClearFilter) -> io::Result<usize> {
+        history::clear_history(&self.path, filter)
+    }
+
+    fn compact(&self, retention_secs: u64, now: u64) -> io::Result<CompactStats> {
+        history::compact_history(&self.path, retention_secs, now)
+    }
+
+    fn needs_compaction(&self, retention_secs: u64, now: u64) -> bool {
+        history::needs_compaction(&self.path, retention_secs, now)
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// Open the configured history store
+pub fn open(storage: Storage) -> io::Result<Box<dyn HistoryStore>> {
+    let path = get_log_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no location for the history"))?;
+
+    match storage {
+        Storage::Jsonl => Ok(Box::new(JsonlStore::new(path, history::get_peer_logs()))),
+        #[cfg(feature = "sqlite")]
+        Storage::Sqlite => Ok(Box::new(crate::sqlite::SqliteStore::open(&sqlite_path(&path))?)),
+        #[cfg(not(feature = "sqlite"))]
+        Storage::Sqlite => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SQLite storage requires building with `--features sqlite`",
+        )),
+    }
+}
+
+/// The SQLite database kept next to the JSONL history path
+#[cfg(feature = "sqlite")]
+pub fn sqlite_path(log_path: &std::path::Path) -> PathBuf {
+    log_path.with_extension("sqlite3")
+}
+
+/// Compact the history on a background thread when it has grown past the retention window
+pub fn spawn_auto_compaction(storage: Storage, retention_secs: u64) {
+    std::thread::spawn(move || {
+        let Ok(store) = open(storage) else {
+            return;
+        };
+        let now = current_timestamp();
+        if store.needs_compaction(retention_secs, now) {
+            let _ = store.compact(retention_secs, now);
+        }
+    });
+}
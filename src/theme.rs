@@ -0,0 +1,157 @@
+use std::fs;
+
+use serde::Deserialize;
+use slint::Color;
+
+use crate::active_app::output;
+use crate::config::get_config_dir;
+
+/// Colors and font of the picker, applied to the `Theme` global in `ui/main.slint`
+pub struct Theme {
+    pub background: Color,
+    pub text: Color,
+    /// Placeholder and label text
+    pub muted: Color,
+    /// Key hints and markers
+    pub hint: Color,
+    /// Highlighted row or related emoji
+    pub selection: Color,
+    pub separator: Color,
+    pub error: Color,
+    pub font_family: String,
+}
+
+const fn rgb(hex: u32) -> Color {
+    Color::from_rgb_u8((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+fn bundled(name: &str) -> Option<Theme> {
+    let (background, text, muted, hint, selection, separator, error) = match name {
+        "light" => (0xd4e5f7, 0x000000, 0x666666, 0x888888, 0xa6d2ff, 0x888888, 0xb00020),
+        "dark" => (0x23262e, 0xe8e8e8, 0xa0a4ab, 0x80858d, 0x2f4f7a, 0x4a4f59, 0xff6b6b),
+        "high-contrast" => (0x000000, 0xffffff, 0xffffff, 0xffff00, 0x0040c0, 0xffffff, 0xff5050),
+        _ => return None,
+    };
+    Some(Theme {
+        background: rgb(background),
+        text: rgb(text),
+        muted: rgb(muted),
+        hint: rgb(hint),
+        selection: rgb(selection),
+        separator: rgb(separator),
+        error: rgb(error),
+        font_family: "Helvetica".to_string(),
+    })
+}
+
+/// A user theme in `~/.emoru/themes/<name>.json`: a bundled `base` theme
+/// (light by default) with some of its colors (`#rrggbb` or `#rrggbbaa`) or
+/// its font replaced
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ThemeFile {
+    base: Option<String>,
+    background: Option<String>,
+    text: Option<String>,
+    muted: Option<String>,
+    hint: Option<String>,
+    selection: Option<String>,
+    separator: Option<String>,
+    error: Option<String>,
+    font_family: Option<String>,
+}
+
+fn parse_color(hex: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color '{}', expected #rrggbb or #rrggbbaa", hex);
+    let digits = hex.strip_prefix('#').ok_or_else(invalid)?;
+    let value = u32::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    match digits.len() {
+        6 => Ok(rgb(value)),
+        8 => Ok(Color::from_argb_u8(value as u8, (value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8)),
+        _ => Err(invalid()),
+    }
+}
+
+fn load_file(name: &str) -> Result<Theme, String> {
+    let path = get_config_dir()
+        .map(|d| d.join("themes").join(format!("{}.json", name)))
+        .ok_or("no home directory")?;
+    let content = fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let file: ThemeFile = serde_json::from_str(&content).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+
+    let base = file.base.as_deref().unwrap_or("light");
+    let mut theme = bundled(base).ok_or_else(|| format!("unknown base theme '{}'", base))?;
+    for (value, field) in [
+        (&file.background, &mut theme.background),
+        (&file.text, &mut theme.text),
+        (&file.muted, &mut theme.muted),
+        (&file.hint, &mut theme.hint),
+        (&file.selection, &mut theme.selection),
+        (&file.separator, &mut theme.separator),
+        (&file.error, &mut theme.error),
+    ] {
+        if let Some(value) = value {
+            *field = parse_color(value)?;
+        }
+    }
+    if let Some(font_family) = file.font_family {
+        theme.font_family = font_family;
+    }
+    Ok(theme)
+}
+
+/// The theme named in the config: `auto` (light or dark, following the
+/// desktop), a bundled theme, or a user theme file. Falls back to the light
+/// theme with a warning.
+pub fn load(name: &str) -> Theme {
+    let name = if name == "auto" {
+        if prefers_dark() { "dark" } else { "light" }
+    } else {
+        name
+    };
+    bundled(name).unwrap_or_else(|| {
+        load_file(name).unwrap_or_else(|e| {
+            eprintln!("emoru: theme '{}': {}", name, e);
+            bundled("light").expect("light theme is bundled")
+        })
+    })
+}
+
+/// Whether the desktop asks for a dark appearance: the `GTK_THEME`
+/// variable, the freedesktop portal's `color-scheme` setting, GNOME's
+/// settings, or the macOS appearance
+pub fn prefers_dark() -> bool {
+    if cfg!(target_os = "macos") {
+        return output("defaults", &["read", "-g", "AppleInterfaceStyle"]).is_some_and(|s| s == "Dark");
+    }
+
+    if let Ok(gtk_theme) = std::env::var("GTK_THEME") {
+        return gtk_theme.to_lowercase().contains("dark");
+    }
+
+    // `(<<uint32 1>>,)`: 1 prefers dark, 2 prefers light, 0 has no preference
+    let portal = output(
+        "gdbus",
+        &[
+            "call",
+            "--session",
+            "--dest",
+            "org.freedesktop.portal.Desktop",
+            "--object-path",
+            "/org/freedesktop/portal/desktop",
+            "--method",
+            "org.freedesktop.portal.Settings.Read",
+            "org.freedesktop.appearance",
+            "color-scheme",
+        ],
+    );
+    match portal.as_deref().and_then(|s| s.split_whitespace().nth(1)) {
+        Some(scheme) if scheme.starts_with('1') => return true,
+        Some(scheme) if scheme.starts_with('2') => return false,
+        _ => {}
+    }
+
+    let gsettings = |key| output("gsettings", &["get", "org.gnome.desktop.interface", key]);
+    gsettings("color-scheme").is_some_and(|s| s.contains("prefer-dark"))
+        || gsettings("gtk-theme").is_some_and(|s| s.to_lowercase().contains("dark"))
+}